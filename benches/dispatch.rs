@@ -0,0 +1,38 @@
+// run with `cargo bench`; each case reports the best of several runs
+use rlox::{InterpretResult, VM};
+use std::time::{Duration, Instant};
+
+const RUNS: usize = 5;
+
+const FIB: &str = "fun fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); }\n\
+                   var result = fib(25);";
+
+const LOOPS: &str = "var total = 0;\n\
+                     for (var i = 0; i < 1000000; i = i + 1) { total = total + i; }\n\
+                     fun local() { var n = 0; var i = 0; while (i < 1000000) { n = n + i; i = i + 1; } return n; }\n\
+                     var result = local();";
+
+const STRING_CONCAT: &str = "var text = \"\";\n\
+                             for (var i = 0; i < 5000; i = i + 1) { text = text + \"ab\"; }\n\
+                             var parts = 0;\n\
+                             for (var i = 0; i < 200000; i = i + 1) { var s = \"x\" + \"y\"; if (s == \"xy\") parts = parts + 1; }";
+
+fn bench(name: &str, source: &str) {
+    let mut best: Duration = Duration::MAX;
+    for _ in 0..RUNS {
+        let mut vm = VM::new();
+        let start: Instant = Instant::now();
+        match vm.interpret_source(source) {
+            InterpretResult::Success => {}
+            result => panic!("{} failed: {}", name, result.to_string()),
+        }
+        best = best.min(start.elapsed());
+    }
+    println!("{:<16}{:>10.2} ms", name, best.as_secs_f64() * 1000.0);
+}
+
+fn main() {
+    bench("fib", FIB);
+    bench("loops", LOOPS);
+    bench("string_concat", STRING_CONCAT);
+}
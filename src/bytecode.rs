@@ -0,0 +1,213 @@
+use crate::chunk::Chunk;
+use crate::function::Function;
+use std::rc::Rc;
+
+// file layout, all integers little-endian:
+//   magic "RLOX", version u16, then the script function
+//   function: name, params_num u32, upvalue_count u32, chunk
+//   chunk: code, lines, numbers, strings, variables, functions
+// every list is a u32 count followed by its items
+pub const MAGIC: &[u8; 4] = b"RLOX";
+pub const VERSION: u16 = 1;
+// loading and verifying recurse once per nested function, so a hostile file
+// could otherwise run the host out of native stack
+pub const NESTING_MAX: usize = 256;
+
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn u16(&mut self, value: u16) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn count(&mut self, count: usize) {
+        self.u32(count as u32);
+    }
+
+    fn string(&mut self, string: &str) {
+        self.count(string.len());
+        self.bytes.extend_from_slice(string.as_bytes());
+    }
+
+    fn function(&mut self, function: &Function) {
+        self.string(&function.name);
+        self.count(function.params_num);
+        self.count(function.upvalue_count);
+        self.chunk(&function.chunk);
+    }
+
+    fn chunk(&mut self, chunk: &Chunk) {
+        self.count(chunk.code().len());
+        self.bytes.extend_from_slice(chunk.code());
+        self.count(chunk.lines().len());
+        for line in chunk.lines() {
+            self.u32(*line);
+        }
+        self.count(chunk.numbers().len());
+        for number in chunk.numbers() {
+            self.u64(number.to_bits());
+        }
+        self.count(chunk.strings().len());
+        for string in chunk.strings() {
+            self.string(string);
+        }
+        self.count(chunk.variables().len());
+        for variable in chunk.variables() {
+            self.count(*variable);
+        }
+        self.count(chunk.functions().len());
+        for function in chunk.functions() {
+            self.function(function);
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    // functions currently being read, the script counts as one
+    depth: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        match self.bytes.len() - self.offset >= len {
+            true => {
+                let slice: &'a [u8] = &self.bytes[self.offset..self.offset + len];
+                self.offset += len;
+                Ok(slice)
+            }
+            false => Err(format!(
+                "Unexpected end of bytecode at byte {}",
+                self.offset
+            )),
+        }
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let bytes: &[u8] = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let bytes: &[u8] = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        let low: u64 = self.u32()?.into();
+        let high: u64 = self.u32()?.into();
+        Ok(low | (high << 32))
+    }
+
+    fn count(&mut self) -> Result<usize, String> {
+        Ok(self.u32()? as usize)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let len: usize = self.count()?;
+        let offset: usize = self.offset;
+        match String::from_utf8(self.take(len)?.to_vec()) {
+            Ok(string) => Ok(string),
+            Err(_) => Err(format!("Invalid utf-8 string at byte {}", offset)),
+        }
+    }
+
+    fn function(&mut self) -> Result<Function, String> {
+        if self.depth >= NESTING_MAX {
+            return Err(format!(
+                "Functions nested too deeply at byte {}, the limit is {}",
+                self.offset, NESTING_MAX
+            ));
+        }
+        self.depth += 1;
+        let function: Function = Function {
+            name: self.string()?,
+            params_num: self.count()?,
+            upvalue_count: self.count()?,
+            chunk: Rc::new(self.chunk()?),
+        };
+        self.depth -= 1;
+        Ok(function)
+    }
+
+    fn chunk(&mut self) -> Result<Chunk, String> {
+        let code_len: usize = self.count()?;
+        let code: Vec<u8> = self.take(code_len)?.to_vec();
+        let lines_len: usize = self.count()?;
+        if lines_len != code_len {
+            return Err(format!(
+                "Chunk has {} code bytes but {} lines",
+                code_len, lines_len
+            ));
+        }
+        let lines: Vec<u32> = (0..lines_len)
+            .map(|_| self.u32())
+            .collect::<Result<_, _>>()?;
+        let numbers_len: usize = self.count()?;
+        let numbers: Vec<f64> = (0..numbers_len)
+            .map(|_| self.u64().map(f64::from_bits))
+            .collect::<Result<_, _>>()?;
+        let strings_len: usize = self.count()?;
+        let strings: Vec<Rc<str>> = (0..strings_len)
+            .map(|_| self.string().map(Rc::from))
+            .collect::<Result<_, _>>()?;
+        let variables_len: usize = self.count()?;
+        let variables: Vec<usize> = (0..variables_len)
+            .map(|_| self.count())
+            .collect::<Result<_, _>>()?;
+        let functions_len: usize = self.count()?;
+        let functions: Vec<Rc<Function>> = (0..functions_len)
+            .map(|_| self.function().map(Rc::new))
+            .collect::<Result<_, _>>()?;
+        Ok(Chunk::from_parts(
+            code, lines, numbers, strings, variables, functions,
+        ))
+    }
+}
+
+impl Function {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer { bytes: Vec::new() };
+        writer.bytes.extend_from_slice(MAGIC);
+        writer.u16(VERSION);
+        writer.function(self);
+        writer.bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Function, String> {
+        let mut reader = Reader {
+            bytes,
+            offset: 0,
+            depth: 0,
+        };
+        if reader.take(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+            return Err("Not an rlox bytecode file".to_string());
+        }
+        let version: u16 = reader.u16()?;
+        if version != VERSION {
+            return Err(format!(
+                "Unsupported bytecode version {}, expected {}",
+                version, VERSION
+            ));
+        }
+        let function: Function = reader.function()?;
+        match reader.offset == bytes.len() {
+            true => Ok(function),
+            false => Err(format!(
+                "Trailing bytes after function at byte {}",
+                reader.offset
+            )),
+        }
+    }
+}
@@ -1,4 +1,6 @@
 use crate::function::Function;
+use crate::interner::Interner;
+use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
 
 pub enum OpCode {
@@ -8,7 +10,7 @@ pub enum OpCode {
     False,
     Number,
     String,
-    Function,
+    Closure,
     Not,
     Negate,
     Addition,
@@ -29,6 +31,93 @@ pub enum OpCode {
     Jump,
     JumpBack,
     Call,
+    GetUpvalue,
+    SetUpvalue,
+    CloseUpvalue,
+    Class,
+    GetProperty,
+    SetProperty,
+    Method,
+    Inherit,
+    GetSuper,
+    NumberLong,
+    StringLong,
+    ClosureLong,
+    DefineGlobalLong,
+    GetGlobalLong,
+    SetGlobalLong,
+    GetLocalLong,
+    SetLocalLong,
+    ClassLong,
+    GetPropertyLong,
+    SetPropertyLong,
+    MethodLong,
+    GetSuperLong,
+    JumpFalseWide,
+    JumpWide,
+    JumpBackWide,
+    Modulo,
+    Power,
+    FloorDivide,
+    BitAnd,
+    BitOr,
+    BitXor,
+    BitNot,
+    ShiftLeft,
+    ShiftRight,
+}
+
+// pool index operands wider than one byte use the long variant
+pub const LONG_OPERAND_MAX: usize = 0x1000000;
+
+impl OpCode {
+    pub fn is_long(&self) -> bool {
+        matches!(
+            self,
+            Self::NumberLong
+                | Self::StringLong
+                | Self::ClosureLong
+                | Self::DefineGlobalLong
+                | Self::GetGlobalLong
+                | Self::SetGlobalLong
+                | Self::GetLocalLong
+                | Self::SetLocalLong
+                | Self::ClassLong
+                | Self::GetPropertyLong
+                | Self::SetPropertyLong
+                | Self::MethodLong
+                | Self::GetSuperLong
+        )
+    }
+
+    pub fn into_long(self) -> Self {
+        match self {
+            Self::Number => Self::NumberLong,
+            Self::String => Self::StringLong,
+            Self::Closure => Self::ClosureLong,
+            Self::DefineGlobal => Self::DefineGlobalLong,
+            Self::GetGlobal => Self::GetGlobalLong,
+            Self::SetGlobal => Self::SetGlobalLong,
+            Self::GetLocal => Self::GetLocalLong,
+            Self::SetLocal => Self::SetLocalLong,
+            Self::Class => Self::ClassLong,
+            Self::GetProperty => Self::GetPropertyLong,
+            Self::SetProperty => Self::SetPropertyLong,
+            Self::Method => Self::MethodLong,
+            Self::GetSuper => Self::GetSuperLong,
+            _ => self,
+        }
+    }
+
+    // jumps with a 32-bit distance
+    pub fn into_wide(self) -> Self {
+        match self {
+            Self::JumpFalse => Self::JumpFalseWide,
+            Self::Jump => Self::JumpWide,
+            Self::JumpBack => Self::JumpBackWide,
+            _ => self,
+        }
+    }
 }
 
 impl From<OpCode> for u8 {
@@ -37,16 +126,18 @@ impl From<OpCode> for u8 {
     }
 }
 
-impl From<u8> for OpCode {
-    fn from(value: u8) -> Self {
-        match value {
+impl TryFrom<u8> for OpCode {
+    type Error = String;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(match value {
             0 => Self::Return,
             1 => Self::Nil,
             2 => Self::True,
             3 => Self::False,
             4 => Self::Number,
             5 => Self::String,
-            6 => Self::Function,
+            6 => Self::Closure,
             7 => Self::Not,
             8 => Self::Negate,
             9 => Self::Addition,
@@ -67,8 +158,42 @@ impl From<u8> for OpCode {
             24 => Self::Jump,
             25 => Self::JumpBack,
             26 => Self::Call,
-            _ => unimplemented!("Invalid OpCode"),
-        }
+            27 => Self::GetUpvalue,
+            28 => Self::SetUpvalue,
+            29 => Self::CloseUpvalue,
+            30 => Self::Class,
+            31 => Self::GetProperty,
+            32 => Self::SetProperty,
+            33 => Self::Method,
+            34 => Self::Inherit,
+            35 => Self::GetSuper,
+            36 => Self::NumberLong,
+            37 => Self::StringLong,
+            38 => Self::ClosureLong,
+            39 => Self::DefineGlobalLong,
+            40 => Self::GetGlobalLong,
+            41 => Self::SetGlobalLong,
+            42 => Self::GetLocalLong,
+            43 => Self::SetLocalLong,
+            44 => Self::ClassLong,
+            45 => Self::GetPropertyLong,
+            46 => Self::SetPropertyLong,
+            47 => Self::MethodLong,
+            48 => Self::GetSuperLong,
+            49 => Self::JumpFalseWide,
+            50 => Self::JumpWide,
+            51 => Self::JumpBackWide,
+            52 => Self::Modulo,
+            53 => Self::Power,
+            54 => Self::FloorDivide,
+            55 => Self::BitAnd,
+            56 => Self::BitOr,
+            57 => Self::BitXor,
+            58 => Self::BitNot,
+            59 => Self::ShiftLeft,
+            60 => Self::ShiftRight,
+            _ => return Err(format!("Invalid opcode {}", value)),
+        })
     }
 }
 
@@ -81,7 +206,7 @@ impl ToString for OpCode {
             Self::False => "OP_FALSE".to_string(),
             Self::Number => "OP_NUMBER".to_string(),
             Self::String => "OP_STRING".to_string(),
-            Self::Function => "OP_FUNCTION".to_string(),
+            Self::Closure => "OP_CLOSURE".to_string(),
             Self::Not => "OP_NOT".to_string(),
             Self::Negate => "OP_NEGATE".to_string(),
             Self::Addition => "OP_ADDITION".to_string(),
@@ -102,17 +227,56 @@ impl ToString for OpCode {
             Self::Jump => "OP_JUMP".to_string(),
             Self::JumpBack => "OP_JUMP_BACK".to_string(),
             Self::Call => "OP_CALL".to_string(),
+            Self::GetUpvalue => "OP_GET_UPVALUE".to_string(),
+            Self::SetUpvalue => "OP_SET_UPVALUE".to_string(),
+            Self::CloseUpvalue => "OP_CLOSE_UPVALUE".to_string(),
+            Self::Class => "OP_CLASS".to_string(),
+            Self::GetProperty => "OP_GET_PROPERTY".to_string(),
+            Self::SetProperty => "OP_SET_PROPERTY".to_string(),
+            Self::Method => "OP_METHOD".to_string(),
+            Self::Inherit => "OP_INHERIT".to_string(),
+            Self::GetSuper => "OP_GET_SUPER".to_string(),
+            Self::NumberLong => "OP_NUMBER_LONG".to_string(),
+            Self::StringLong => "OP_STRING_LONG".to_string(),
+            Self::ClosureLong => "OP_CLOSURE_LONG".to_string(),
+            Self::DefineGlobalLong => "OP_DEFINE_GLOBAL_LONG".to_string(),
+            Self::GetGlobalLong => "OP_GET_GLOBAL_LONG".to_string(),
+            Self::SetGlobalLong => "OP_SET_GLOBAL_LONG".to_string(),
+            Self::GetLocalLong => "OP_GET_LOCAL_LONG".to_string(),
+            Self::SetLocalLong => "OP_SET_LOCAL_LONG".to_string(),
+            Self::ClassLong => "OP_CLASS_LONG".to_string(),
+            Self::GetPropertyLong => "OP_GET_PROPERTY_LONG".to_string(),
+            Self::SetPropertyLong => "OP_SET_PROPERTY_LONG".to_string(),
+            Self::MethodLong => "OP_METHOD_LONG".to_string(),
+            Self::GetSuperLong => "OP_GET_SUPER_LONG".to_string(),
+            Self::JumpFalseWide => "OP_JUMP_FALSE_WIDE".to_string(),
+            Self::JumpWide => "OP_JUMP_WIDE".to_string(),
+            Self::JumpBackWide => "OP_JUMP_BACK_WIDE".to_string(),
+            Self::Modulo => "OP_MODULO".to_string(),
+            Self::Power => "OP_POWER".to_string(),
+            Self::FloorDivide => "OP_FLOOR_DIVIDE".to_string(),
+            Self::BitAnd => "OP_BIT_AND".to_string(),
+            Self::BitOr => "OP_BIT_OR".to_string(),
+            Self::BitXor => "OP_BIT_XOR".to_string(),
+            Self::BitNot => "OP_BIT_NOT".to_string(),
+            Self::ShiftLeft => "OP_SHIFT_LEFT".to_string(),
+            Self::ShiftRight => "OP_SHIFT_RIGHT".to_string(),
         }
     }
 }
 
+#[derive(Clone)]
 pub struct Chunk {
     code: Vec<u8>,
     numbers: Vec<f64>,
-    strings: Vec<String>,
+    strings: Vec<Rc<str>>,
     functions: Vec<Rc<Function>>,
     variables: Vec<usize>,
     lines: Vec<u32>,
+    // pool lookups so repeated constants share one index
+    number_indices: HashMap<u64, usize>,
+    string_indices: HashMap<Rc<str>, usize>,
+    variable_indices: HashMap<usize, usize>,
 }
 
 impl Chunk {
@@ -120,35 +284,95 @@ impl Chunk {
         Self {
             code: Vec::<u8>::new(),
             numbers: Vec::<f64>::new(),
-            strings: Vec::<String>::new(),
+            strings: Vec::<Rc<str>>::new(),
             variables: Vec::<usize>::new(),
             functions: Vec::<Rc<Function>>::new(),
             lines: Vec::<u32>::new(),
+            number_indices: HashMap::<u64, usize>::new(),
+            string_indices: HashMap::<Rc<str>, usize>::new(),
+            variable_indices: HashMap::<usize, usize>::new(),
         }
     }
 
+    // a chunk read back from bytecode, pools taken as they are
+    pub fn from_parts(
+        code: Vec<u8>,
+        lines: Vec<u32>,
+        numbers: Vec<f64>,
+        strings: Vec<Rc<str>>,
+        variables: Vec<usize>,
+        functions: Vec<Rc<Function>>,
+    ) -> Self {
+        let mut chunk: Chunk = Self::new();
+        chunk.number_indices = numbers
+            .iter()
+            .enumerate()
+            .map(|(i, n)| (n.to_bits(), i))
+            .collect();
+        chunk.string_indices = strings
+            .iter()
+            .enumerate()
+            .map(|(i, s)| (s.clone(), i))
+            .collect();
+        chunk.variable_indices = variables.iter().enumerate().map(|(i, v)| (*v, i)).collect();
+        chunk.code = code;
+        chunk.lines = lines;
+        chunk.numbers = numbers;
+        chunk.strings = strings;
+        chunk.variables = variables;
+        chunk.functions = functions;
+        chunk
+    }
+
     pub fn write_code(&mut self, byte: u8, line: u32) {
         self.code.push(byte);
         self.lines.push(line);
     }
 
+    // one byte index when it fits, otherwise the long variant with a 24-bit index
+    pub fn write_index_code(&mut self, op_code: OpCode, index: usize, line: u32) {
+        match index < 0x100 {
+            true => {
+                self.write_code(op_code.into(), line);
+                self.write_code(index as u8, line);
+            }
+            false => {
+                self.write_code(op_code.into_long().into(), line);
+                self.write_code(index as u8, line);
+                self.write_code((index >> 8) as u8, line);
+                self.write_code((index >> 16) as u8, line);
+            }
+        }
+    }
+
     pub fn update_code(&mut self, offset: usize, byte: u8) {
         self.code[offset] = byte;
     }
 
     pub fn add_number(&mut self, number: f64) -> Result<usize, String> {
-        match self.numbers.len() < 0x100 {
+        // by bit pattern, so 0 and -0 stay distinct
+        if let Some(index) = self.number_indices.get(&number.to_bits()) {
+            return Ok(*index);
+        }
+        match self.numbers.len() < LONG_OPERAND_MAX {
             true => {
                 self.numbers.push(number);
+                self.number_indices
+                    .insert(number.to_bits(), self.numbers.len() - 1);
                 Ok(self.numbers.len() - 1)
             }
             false => Err("Too many numbers in one chunk".to_string()),
         }
     }
 
-    pub fn add_string(&mut self, string: String) -> Result<usize, String> {
-        match self.strings.len() < 0x100 {
+    pub fn add_string(&mut self, string: Rc<str>) -> Result<usize, String> {
+        if let Some(index) = self.string_indices.get(&string) {
+            return Ok(*index);
+        }
+        match self.strings.len() < LONG_OPERAND_MAX {
             true => {
+                self.string_indices
+                    .insert(string.clone(), self.strings.len());
                 self.strings.push(string);
                 Ok(self.strings.len() - 1)
             }
@@ -157,9 +381,14 @@ impl Chunk {
     }
 
     pub fn add_variable(&mut self, variable: usize) -> Result<usize, String> {
-        match self.variables.len() < 0x100 {
+        if let Some(index) = self.variable_indices.get(&variable) {
+            return Ok(*index);
+        }
+        match self.variables.len() < LONG_OPERAND_MAX {
             true => {
                 self.variables.push(variable);
+                self.variable_indices
+                    .insert(variable, self.variables.len() - 1);
                 Ok(self.variables.len() - 1)
             }
             false => Err("Too many variable slots in one chunk".to_string()),
@@ -167,7 +396,7 @@ impl Chunk {
     }
 
     pub fn add_function(&mut self, function: Rc<Function>) -> Result<usize, String> {
-        match self.functions.len() < 0x100 {
+        match self.functions.len() < LONG_OPERAND_MAX {
             true => {
                 self.functions.push(function);
                 Ok(self.functions.len() - 1)
@@ -176,27 +405,70 @@ impl Chunk {
         }
     }
 
+    // swap string constants for the interned copies, nested functions included,
+    // a function or chunk shared with someone else is copied rather than skipped
+    pub fn intern_strings(&mut self, interner: &mut Interner) {
+        for string in self.strings.iter_mut() {
+            *string = interner.intern(string);
+        }
+        for function in self.functions.iter_mut() {
+            Rc::make_mut(&mut Rc::make_mut(function).chunk).intern_strings(interner);
+        }
+    }
+
     pub fn clear(&mut self) {
         self.code.clear();
         self.numbers.clear();
         self.strings.clear();
         self.variables.clear();
         self.lines.clear();
+        self.number_indices.clear();
+        self.string_indices.clear();
+        self.variable_indices.clear();
     }
 
     pub fn code_size(&self) -> usize {
         self.code.len()
     }
 
+    pub fn code(&self) -> &[u8] {
+        &self.code
+    }
+
+    pub fn lines(&self) -> &[u32] {
+        &self.lines
+    }
+
+    pub fn numbers(&self) -> &[f64] {
+        &self.numbers
+    }
+
+    pub fn strings(&self) -> &[Rc<str>] {
+        &self.strings
+    }
+
+    pub fn variables(&self) -> &[usize] {
+        &self.variables
+    }
+
+    pub fn functions(&self) -> &[Rc<Function>] {
+        &self.functions
+    }
+
     pub fn read_code(&self, offset: usize) -> u8 {
         self.code[offset]
     }
 
+    // only for code from the compiler or code that passed the verifier
+    pub fn read_op_code(&self, offset: usize) -> OpCode {
+        OpCode::try_from(self.code[offset]).unwrap()
+    }
+
     pub fn read_number(&self, offset: usize) -> &f64 {
         &self.numbers[offset]
     }
 
-    pub fn read_string(&self, offset: usize) -> &String {
+    pub fn read_string(&self, offset: usize) -> &Rc<str> {
         &self.strings[offset]
     }
 
@@ -211,6 +483,139 @@ impl Chunk {
     pub fn read_line(&self, offset: usize) -> &u32 {
         &self.lines[offset]
     }
+
+    pub fn read_index(&self, instruction: &OpCode, offset: usize) -> usize {
+        match instruction.is_long() {
+            true => {
+                self.code[offset] as usize
+                    | (self.code[offset + 1] as usize) << 8
+                    | (self.code[offset + 2] as usize) << 16
+            }
+            false => self.code[offset] as usize,
+        }
+    }
+
+    // byte length of the instruction at offset, operands included
+    pub fn instruction_size(&self, offset: usize) -> usize {
+        let instruction: OpCode = self.read_op_code(offset);
+        match instruction {
+            OpCode::Closure | OpCode::ClosureLong => {
+                let index: usize = self.read_index(&instruction, offset + 1);
+                let upvalue_count: usize = self.functions[index].upvalue_count;
+                match instruction.is_long() {
+                    true => 4 + upvalue_count * 2,
+                    false => 2 + upvalue_count * 2,
+                }
+            }
+            OpCode::Number
+            | OpCode::String
+            | OpCode::DefineGlobal
+            | OpCode::GetGlobal
+            | OpCode::SetGlobal
+            | OpCode::GetLocal
+            | OpCode::SetLocal
+            | OpCode::Call
+            | OpCode::GetUpvalue
+            | OpCode::SetUpvalue
+            | OpCode::Class
+            | OpCode::GetProperty
+            | OpCode::SetProperty
+            | OpCode::Method
+            | OpCode::GetSuper => 2,
+            OpCode::JumpFalse | OpCode::Jump | OpCode::JumpBack => 3,
+            OpCode::JumpFalseWide | OpCode::JumpWide | OpCode::JumpBackWide => 5,
+            _ if instruction.is_long() => 4,
+            _ => 1,
+        }
+    }
+
+    // relaxation pass: rewrite the given jumps (offset -> target) into the wide
+    // form, then widen any other jump pushed out of u16 range by the growth
+    pub fn widen_jumps(&mut self, wide_targets: &HashMap<usize, usize>) {
+        let mut offsets: Vec<usize> = Vec::new();
+        let mut targets: HashMap<usize, usize> = HashMap::new();
+        let mut offset: usize = 0;
+        while offset < self.code.len() {
+            offsets.push(offset);
+            let instruction: OpCode = self.read_op_code(offset);
+            if let OpCode::JumpFalse | OpCode::Jump | OpCode::JumpBack = instruction {
+                let target: usize = match wide_targets.get(&offset) {
+                    Some(target) => *target,
+                    None => {
+                        let distance: usize =
+                            self.code[offset + 1] as usize | (self.code[offset + 2] as usize) << 8;
+                        match instruction {
+                            OpCode::JumpBack => offset + 3 - distance,
+                            _ => offset + 3 + distance,
+                        }
+                    }
+                };
+                targets.insert(offset, target);
+            }
+            offset += self.instruction_size(offset);
+        }
+
+        // old offset -> new offset, grown until every short jump fits
+        let mut wide: HashSet<usize> = wide_targets.keys().cloned().collect();
+        let mut new_offsets: HashMap<usize, usize>;
+        loop {
+            new_offsets = HashMap::new();
+            let mut new_offset: usize = 0;
+            for offset in offsets.iter() {
+                new_offsets.insert(*offset, new_offset);
+                new_offset += match wide.contains(offset) {
+                    true => 5,
+                    false => self.instruction_size(*offset),
+                };
+            }
+            new_offsets.insert(self.code.len(), new_offset);
+
+            let overflow: Vec<usize> = targets
+                .iter()
+                .filter(|(offset, target)| {
+                    !wide.contains(offset)
+                        && (new_offsets[offset] + 3).abs_diff(new_offsets[target])
+                            > u16::MAX as usize
+                })
+                .map(|(offset, _)| *offset)
+                .collect();
+            if overflow.is_empty() {
+                break;
+            }
+            wide.extend(overflow);
+        }
+
+        let mut code: Vec<u8> = Vec::<u8>::new();
+        let mut lines: Vec<u32> = Vec::<u32>::new();
+        for offset in offsets {
+            let size: usize = self.instruction_size(offset);
+            let line: u32 = self.lines[offset];
+            match targets.get(&offset) {
+                Some(target) => {
+                    let instruction: OpCode = self.read_op_code(offset);
+                    let (instruction, operand_size): (OpCode, usize) = match wide.contains(&offset)
+                    {
+                        true => (instruction.into_wide(), 4),
+                        false => (instruction, 2),
+                    };
+                    let distance: usize =
+                        (new_offsets[&offset] + 1 + operand_size).abs_diff(new_offsets[target]);
+                    code.push(instruction.into());
+                    lines.push(line);
+                    for i in 0..operand_size {
+                        code.push((distance >> (i * 8)) as u8);
+                        lines.push(line);
+                    }
+                }
+                None => {
+                    code.extend_from_slice(&self.code[offset..offset + size]);
+                    lines.extend_from_slice(&self.lines[offset..offset + size]);
+                }
+            }
+        }
+        self.code = code;
+        self.lines = lines;
+    }
 }
 
 pub trait Disassemble {
@@ -219,6 +624,8 @@ pub trait Disassemble {
     fn one_instruction(&self, instruction: OpCode, offset: usize) -> usize;
     fn two_instruction(&self, instruction: OpCode, offset: usize) -> usize;
     fn three_instruction(&self, instruction: OpCode, offset: usize) -> usize;
+    fn closure_instruction(&self, instruction: OpCode, offset: usize) -> usize;
+    fn five_instruction(&self, instruction: OpCode, offset: usize) -> usize;
 }
 
 impl Disassemble for Chunk {
@@ -232,7 +639,7 @@ impl Disassemble for Chunk {
     }
 
     fn disassemble_instruction(&self, offset: usize) -> usize {
-        let instruction: OpCode = self.code[offset].into();
+        let instruction: OpCode = self.read_op_code(offset);
         match instruction {
             OpCode::Return => self.one_instruction(instruction, offset),
             OpCode::Nil => self.one_instruction(instruction, offset),
@@ -240,7 +647,7 @@ impl Disassemble for Chunk {
             OpCode::False => self.one_instruction(instruction, offset),
             OpCode::Number => self.two_instruction(instruction, offset),
             OpCode::String => self.two_instruction(instruction, offset),
-            OpCode::Function => self.two_instruction(instruction, offset),
+            OpCode::Closure => self.closure_instruction(instruction, offset),
             OpCode::Not => self.one_instruction(instruction, offset),
             OpCode::Negate => self.one_instruction(instruction, offset),
             OpCode::Addition => self.one_instruction(instruction, offset),
@@ -261,6 +668,40 @@ impl Disassemble for Chunk {
             OpCode::Jump => self.three_instruction(instruction, offset),
             OpCode::JumpBack => self.three_instruction(instruction, offset),
             OpCode::Call => self.two_instruction(instruction, offset),
+            OpCode::GetUpvalue => self.two_instruction(instruction, offset),
+            OpCode::SetUpvalue => self.two_instruction(instruction, offset),
+            OpCode::CloseUpvalue => self.one_instruction(instruction, offset),
+            OpCode::Class => self.two_instruction(instruction, offset),
+            OpCode::GetProperty => self.two_instruction(instruction, offset),
+            OpCode::SetProperty => self.two_instruction(instruction, offset),
+            OpCode::Method => self.two_instruction(instruction, offset),
+            OpCode::Inherit => self.one_instruction(instruction, offset),
+            OpCode::GetSuper => self.two_instruction(instruction, offset),
+            OpCode::NumberLong => self.two_instruction(instruction, offset),
+            OpCode::StringLong => self.two_instruction(instruction, offset),
+            OpCode::ClosureLong => self.closure_instruction(instruction, offset),
+            OpCode::DefineGlobalLong => self.two_instruction(instruction, offset),
+            OpCode::GetGlobalLong => self.two_instruction(instruction, offset),
+            OpCode::SetGlobalLong => self.two_instruction(instruction, offset),
+            OpCode::GetLocalLong => self.two_instruction(instruction, offset),
+            OpCode::SetLocalLong => self.two_instruction(instruction, offset),
+            OpCode::ClassLong => self.two_instruction(instruction, offset),
+            OpCode::GetPropertyLong => self.two_instruction(instruction, offset),
+            OpCode::SetPropertyLong => self.two_instruction(instruction, offset),
+            OpCode::MethodLong => self.two_instruction(instruction, offset),
+            OpCode::GetSuperLong => self.two_instruction(instruction, offset),
+            OpCode::JumpFalseWide => self.five_instruction(instruction, offset),
+            OpCode::JumpWide => self.five_instruction(instruction, offset),
+            OpCode::JumpBackWide => self.five_instruction(instruction, offset),
+            OpCode::Modulo => self.one_instruction(instruction, offset),
+            OpCode::Power => self.one_instruction(instruction, offset),
+            OpCode::FloorDivide => self.one_instruction(instruction, offset),
+            OpCode::BitAnd => self.one_instruction(instruction, offset),
+            OpCode::BitOr => self.one_instruction(instruction, offset),
+            OpCode::BitXor => self.one_instruction(instruction, offset),
+            OpCode::BitNot => self.one_instruction(instruction, offset),
+            OpCode::ShiftLeft => self.one_instruction(instruction, offset),
+            OpCode::ShiftRight => self.one_instruction(instruction, offset),
         }
     }
 
@@ -275,27 +716,48 @@ impl Disassemble for Chunk {
     }
 
     fn two_instruction(&self, instruction: OpCode, offset: usize) -> usize {
-        let constant_offset: usize = self.code[offset + 1].into();
+        let constant_offset: usize = self.read_index(&instruction, offset + 1);
+        let next_offset: usize = match instruction.is_long() {
+            true => offset + 4,
+            false => offset + 2,
+        };
         println!(
             "line:{}  code:{}    {}    {}",
             self.lines[offset],
             offset,
             instruction.to_string(),
             match instruction {
-                OpCode::Number => format!("constant'{}", self.numbers[constant_offset].to_string()),
-                OpCode::String => format!("constant'\"{}\"", self.strings[constant_offset]),
-                OpCode::Function =>
-                    format!("constant'{}", self.functions[constant_offset].to_string()),
-                OpCode::DefineGlobal => format!("global_slot'{}", self.variables[constant_offset]),
-                OpCode::GetGlobal => format!("global_slot'{}", self.variables[constant_offset]),
-                OpCode::SetGlobal => format!("global_slot'{}", self.variables[constant_offset]),
-                OpCode::GetLocal => format!("local_slot'{}", self.variables[constant_offset]),
-                OpCode::SetLocal => format!("local_slot'{}", self.variables[constant_offset]),
+                OpCode::Number | OpCode::NumberLong =>
+                    format!("constant'{}", self.numbers[constant_offset].to_string()),
+                OpCode::String | OpCode::StringLong =>
+                    format!("constant'\"{}\"", self.strings[constant_offset]),
+                OpCode::DefineGlobal | OpCode::DefineGlobalLong =>
+                    format!("global'\"{}\"", self.strings[constant_offset]),
+                OpCode::GetGlobal | OpCode::GetGlobalLong =>
+                    format!("global'\"{}\"", self.strings[constant_offset]),
+                OpCode::SetGlobal | OpCode::SetGlobalLong =>
+                    format!("global'\"{}\"", self.strings[constant_offset]),
+                OpCode::GetLocal | OpCode::GetLocalLong =>
+                    format!("local_slot'{}", self.variables[constant_offset]),
+                OpCode::SetLocal | OpCode::SetLocalLong =>
+                    format!("local_slot'{}", self.variables[constant_offset]),
                 OpCode::Call => format!("params_cout'{}", constant_offset),
+                OpCode::GetUpvalue => format!("upvalue_slot'{}", constant_offset),
+                OpCode::SetUpvalue => format!("upvalue_slot'{}", constant_offset),
+                OpCode::Class | OpCode::ClassLong =>
+                    format!("constant'\"{}\"", self.strings[constant_offset]),
+                OpCode::GetProperty | OpCode::GetPropertyLong =>
+                    format!("property'\"{}\"", self.strings[constant_offset]),
+                OpCode::SetProperty | OpCode::SetPropertyLong =>
+                    format!("property'\"{}\"", self.strings[constant_offset]),
+                OpCode::Method | OpCode::MethodLong =>
+                    format!("method'\"{}\"", self.strings[constant_offset]),
+                OpCode::GetSuper | OpCode::GetSuperLong =>
+                    format!("method'\"{}\"", self.strings[constant_offset]),
                 _ => "".to_string(),
             }
         );
-        offset + 2
+        next_offset
     }
 
     fn three_instruction(&self, instruction: OpCode, offset: usize) -> usize {
@@ -313,4 +775,53 @@ impl Disassemble for Chunk {
         );
         offset + 3
     }
+
+    fn five_instruction(&self, instruction: OpCode, offset: usize) -> usize {
+        let jump_count: usize = (0..4).fold(0, |count, i| {
+            count | (self.code[offset + 1 + i] as usize) << (i * 8)
+        });
+        println!(
+            "line:{}  code:{}    {}    jump_code'{}",
+            self.lines[offset],
+            offset,
+            instruction.to_string(),
+            match instruction {
+                OpCode::JumpBackWide => offset + 5 - jump_count,
+                _ => offset + 5 + jump_count,
+            }
+        );
+        offset + 5
+    }
+
+    fn closure_instruction(&self, instruction: OpCode, offset: usize) -> usize {
+        let function_offset: usize = self.read_index(&instruction, offset + 1);
+        let function: &Rc<Function> = &self.functions[function_offset];
+        let mut upvalue_offset: usize = match instruction.is_long() {
+            true => offset + 4,
+            false => offset + 2,
+        };
+        println!(
+            "line:{}  code:{}    {}    constant'{}",
+            self.lines[offset],
+            offset,
+            instruction.to_string(),
+            function.to_string()
+        );
+        for _ in 0..function.upvalue_count {
+            let is_local: bool = self.code[upvalue_offset] == 1;
+            let index: u8 = self.code[upvalue_offset + 1];
+            println!(
+                "line:{}  code:{}    |    {}'{}",
+                self.lines[upvalue_offset],
+                upvalue_offset,
+                match is_local {
+                    true => "local_slot",
+                    false => "upvalue_slot",
+                },
+                index
+            );
+            upvalue_offset += 2;
+        }
+        upvalue_offset
+    }
 }
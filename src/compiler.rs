@@ -1,9 +1,11 @@
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
 
 use crate::chunk::*;
+use crate::error::CompileError;
 use crate::function::*;
+use crate::interner::Interner;
 use crate::scanner::*;
 use crate::token::*;
 
@@ -13,11 +15,16 @@ enum Precedence {
     Assignment, // =
     Or,         // or
     And,        // and
+    BitOr,      // |
+    BitXor,     // ^
+    BitAnd,     // &
     Equality,   // == !=
     Comparison, // < > <= >=
+    Shift,      // << >>
     Term,       // + -
     Factor,     // * /
     Unary,      // ! -
+    Exponent,   // **
     Call,       // . ()
     Primary,
 }
@@ -35,13 +42,18 @@ impl From<u8> for Precedence {
             1 => Self::Assignment,
             2 => Self::Or,
             3 => Self::And,
-            4 => Self::Equality,
-            5 => Self::Comparison,
-            6 => Self::Term,
-            7 => Self::Factor,
-            8 => Self::Unary,
-            9 => Self::Call,
-            10 => Self::Primary,
+            4 => Self::BitOr,
+            5 => Self::BitXor,
+            6 => Self::BitAnd,
+            7 => Self::Equality,
+            8 => Self::Comparison,
+            9 => Self::Shift,
+            10 => Self::Term,
+            11 => Self::Factor,
+            12 => Self::Unary,
+            13 => Self::Exponent,
+            14 => Self::Call,
+            15 => Self::Primary,
             _ => unimplemented!("Invalid Precedence"),
         }
     }
@@ -92,6 +104,51 @@ static PARSE_RULES: [ParseRule; TokenType::Error as usize] = {
         infix: Some(Compiler::parse_binary),
         precedence: Precedence::Factor,
     };
+    vec[TokenType::Percent as usize] = ParseRule {
+        prefix: None,
+        infix: Some(Compiler::parse_binary),
+        precedence: Precedence::Factor,
+    };
+    vec[TokenType::SlashSlash as usize] = ParseRule {
+        prefix: None,
+        infix: Some(Compiler::parse_binary),
+        precedence: Precedence::Factor,
+    };
+    vec[TokenType::StarStar as usize] = ParseRule {
+        prefix: None,
+        infix: Some(Compiler::parse_binary),
+        precedence: Precedence::Exponent,
+    };
+    vec[TokenType::Ampersand as usize] = ParseRule {
+        prefix: None,
+        infix: Some(Compiler::parse_binary),
+        precedence: Precedence::BitAnd,
+    };
+    vec[TokenType::Pipe as usize] = ParseRule {
+        prefix: None,
+        infix: Some(Compiler::parse_binary),
+        precedence: Precedence::BitOr,
+    };
+    vec[TokenType::Caret as usize] = ParseRule {
+        prefix: None,
+        infix: Some(Compiler::parse_binary),
+        precedence: Precedence::BitXor,
+    };
+    vec[TokenType::Tilde as usize] = ParseRule {
+        prefix: Some(Compiler::parse_unary),
+        infix: None,
+        precedence: Precedence::None,
+    };
+    vec[TokenType::LessLess as usize] = ParseRule {
+        prefix: None,
+        infix: Some(Compiler::parse_binary),
+        precedence: Precedence::Shift,
+    };
+    vec[TokenType::GreaterGreater as usize] = ParseRule {
+        prefix: None,
+        infix: Some(Compiler::parse_binary),
+        precedence: Precedence::Shift,
+    };
     vec[TokenType::Number as usize] = ParseRule {
         prefix: Some(Compiler::parse_number),
         infix: None,
@@ -167,28 +224,84 @@ static PARSE_RULES: [ParseRule; TokenType::Error as usize] = {
         infix: Some(Compiler::parse_or),
         precedence: Precedence::Or,
     };
+    vec[TokenType::Dot as usize] = ParseRule {
+        prefix: None,
+        infix: Some(Compiler::parse_dot),
+        precedence: Precedence::Call,
+    };
+    vec[TokenType::Super as usize] = ParseRule {
+        prefix: Some(Compiler::parse_super),
+        infix: None,
+        precedence: Precedence::None,
+    };
+    vec[TokenType::This as usize] = ParseRule {
+        prefix: Some(Compiler::parse_this),
+        infix: None,
+        precedence: Precedence::None,
+    };
     vec
 };
 
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionType {
+    Script,
+    Function,
+    Method,
+    Initializer,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct UpvalueInfo {
+    is_local: bool,
+    index: usize,
+}
+
+struct ClassContext {
+    has_superclass: bool,
+}
+
+struct LoopContext {
+    // where 'continue' jumps back to
+    start: usize,
+    // scope depth outside the body, deeper locals are dropped on 'break'/'continue'
+    depth: usize,
+    // 'break' jumps, patched once the loop exit is known
+    breaks: Vec<usize>,
+}
+
 struct CompileContext {
     // depth -> local_map(identifier -> index)
     variables: RefCell<HashMap<usize, HashMap<String, usize>>>,
     local_count: RefCell<usize>,
     depth: RefCell<usize>,
+    // local slots captured by inner functions
+    captured: RefCell<HashSet<usize>>,
+    upvalues: RefCell<Vec<UpvalueInfo>>,
+    // jump offset -> target, for jumps too far for a 16-bit operand
+    wide_jumps: RefCell<HashMap<usize, usize>>,
+    // enclosing loops, innermost last
+    loops: RefCell<Vec<LoopContext>>,
 
     // compile result
     chunk: RefCell<Chunk>,
+    function_type: FunctionType,
     function_name: RefCell<String>,
     params_num: RefCell<usize>,
 }
 
 impl CompileContext {
-    fn new() -> Self {
+    fn new(function_type: FunctionType) -> Self {
         Self {
             variables: RefCell::new(HashMap::new()),
-            local_count: RefCell::new(0),
+            // slot 0 holds the callee, or 'this' in methods
+            local_count: RefCell::new(1),
             depth: RefCell::new(0),
+            captured: RefCell::new(HashSet::new()),
+            upvalues: RefCell::new(Vec::new()),
+            wide_jumps: RefCell::new(HashMap::new()),
+            loops: RefCell::new(Vec::new()),
             chunk: RefCell::new(Chunk::new()),
+            function_type,
             function_name: RefCell::new(String::default()),
             params_num: RefCell::new(0),
         }
@@ -200,31 +313,53 @@ pub struct Compiler {
     current: Token,
     previous: Token,
     is_panic: RefCell<bool>,
-    had_error: RefCell<bool>,
+    errors: RefCell<Vec<CompileError>>,
 
     // compile stack
     compile_context_stack: Vec<Rc<CompileContext>>,
+    class_context_stack: Vec<ClassContext>,
+    // print top-level expression statements instead of popping them
+    echo_expressions: bool,
+    // string constants, shared with the VM that runs the result
+    interner: Rc<RefCell<Interner>>,
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Compiler {
     pub fn new() -> Self {
+        Self::with_interner(Rc::new(RefCell::new(Interner::new())))
+    }
+
+    pub(crate) fn with_interner(interner: Rc<RefCell<Interner>>) -> Self {
         Self {
             scanner: Scanner::new(),
             current: Token::default(),
             previous: Token::default(),
             is_panic: RefCell::<bool>::new(false),
-            had_error: RefCell::<bool>::new(false),
+            errors: RefCell::<Vec<CompileError>>::new(Vec::new()),
             compile_context_stack: Vec::<Rc<CompileContext>>::new(),
+            class_context_stack: Vec::<ClassContext>::new(),
+            echo_expressions: false,
+            interner,
         }
     }
 
+    pub fn set_echo_expressions(&mut self, echo_expressions: bool) {
+        self.echo_expressions = echo_expressions;
+    }
+
     fn root_context(&self) -> Rc<CompileContext> {
         self.compile_context_stack.first().unwrap().clone()
     }
 
-    fn push_context(&mut self) {
+    fn push_context(&mut self, function_type: FunctionType) {
         self.compile_context_stack
-            .push(Rc::new(CompileContext::new()));
+            .push(Rc::new(CompileContext::new(function_type)));
     }
 
     fn curr_context(&self) -> Rc<CompileContext> {
@@ -235,7 +370,7 @@ impl Compiler {
         self.compile_context_stack.pop().unwrap()
     }
 
-    pub fn show_tokens(&mut self, source: &String) {
+    pub fn show_tokens(&mut self, source: &str) {
         self.scanner.reset(source);
         loop {
             let token = self.scanner.scan_token();
@@ -252,11 +387,11 @@ impl Compiler {
         }
     }
 
-    pub fn compile(&mut self, source: &String) -> Result<Function, String> {
+    pub fn compile(&mut self, source: &str) -> Result<Function, Vec<CompileError>> {
         self.scanner.reset(source);
         // compile context push
-        self.push_context();
-        // global variables
+        self.push_context(FunctionType::Script);
+        // global variables declared in this source
         self.root_context()
             .variables
             .borrow_mut()
@@ -269,33 +404,32 @@ impl Compiler {
                 false => self.declaration(),
             }
         }
-        let function: Function = self.compile_end();
+        let (function, _) = self.compile_end();
         self.consume(TokenType::Eof, "Expect end of expression");
 
-        if *self.had_error.borrow() {
-            Err("Compile error".to_string())
-        } else {
-            Ok(function)
+        let errors: Vec<CompileError> = self.errors.replace(Vec::new());
+        match errors.is_empty() {
+            true => Ok(function),
+            false => Err(errors),
         }
     }
 
-    fn compile_end(&mut self) -> Function {
-        self.curr_context()
-            .chunk
-            .borrow_mut()
-            .write_code(OpCode::Nil.into(), self.previous.line);
-        self.curr_context()
-            .chunk
-            .borrow_mut()
-            .write_code(OpCode::Return.into(), self.previous.line);
+    fn compile_end(&mut self) -> (Function, Vec<UpvalueInfo>) {
+        self.emit_return();
         let context = self.pop_context();
+        let upvalues: Vec<UpvalueInfo> = context.upvalues.replace(Vec::new());
+        let wide_jumps: HashMap<usize, usize> = context.wide_jumps.replace(HashMap::new());
+        if !wide_jumps.is_empty() {
+            context.chunk.borrow_mut().widen_jumps(&wide_jumps);
+        }
         let function = Function {
             name: context.function_name.replace(String::new()),
             params_num: context.params_num.replace(0),
+            upvalue_count: upvalues.len(),
             chunk: Rc::new(context.chunk.replace(Chunk::new())),
         };
         // function.disassemble();
-        function
+        (function, upvalues)
     }
 
     fn advance(&mut self) {
@@ -330,14 +464,16 @@ impl Compiler {
             true => return,
             false => {
                 self.is_panic.replace(true);
-                eprint!("[line {}] Error ", token.line);
-                match token.r#type {
-                    TokenType::Eof => eprint!("at end"),
-                    TokenType::Error => eprint!("{}", token.lexeme),
-                    _ => eprint!("at '{}", token.lexeme),
-                }
-                eprintln!(" : {}", message);
-                self.had_error.replace(true);
+                let (lexeme, message) = match token.r#type {
+                    TokenType::Error => (String::new(), format!("{}: {}", message, token.lexeme)),
+                    _ => (token.lexeme.clone(), message.to_string()),
+                };
+                self.errors.borrow_mut().push(CompileError {
+                    line: token.line,
+                    column: token.column,
+                    lexeme,
+                    message,
+                });
             }
         }
     }
@@ -346,13 +482,24 @@ impl Compiler {
         match unsafe { *self.is_panic.as_ptr() } {
             true => {
                 self.is_panic.replace(false);
+                // skip to the next statement boundary, the failed statement may
+                // already have consumed its ';'
                 loop {
+                    if self.previous.r#type == TokenType::Semicolon {
+                        return;
+                    }
                     match self.current.r#type {
-                        TokenType::Eof => return,
-                        TokenType::Semicolon => {
-                            self.advance();
-                            return;
-                        }
+                        TokenType::Eof
+                        | TokenType::Class
+                        | TokenType::Fun
+                        | TokenType::Var
+                        | TokenType::For
+                        | TokenType::If
+                        | TokenType::While
+                        | TokenType::Print
+                        | TokenType::Return
+                        | TokenType::Break
+                        | TokenType::Continue => return,
                         _ => self.advance(),
                     }
                 }
@@ -396,10 +543,22 @@ impl Compiler {
                 self.advance();
                 self.function_statement();
             }
+            TokenType::Class => {
+                self.advance();
+                self.class_statement();
+            }
             TokenType::Return => {
                 self.advance();
                 self.return_statement();
             }
+            TokenType::Break => {
+                self.advance();
+                self.break_statement();
+            }
+            TokenType::Continue => {
+                self.advance();
+                self.continue_statement();
+            }
             _ => self.expression_statement(),
         }
     }
@@ -416,10 +575,17 @@ impl Compiler {
     fn expression_statement(&mut self) {
         self.parse_expression();
         self.consume(TokenType::Semicolon, "Expect ';' after expression");
-        self.curr_context()
+        let context: Rc<CompileContext> = self.curr_context();
+        let is_top_level: bool =
+            context.function_type == FunctionType::Script && *context.depth.borrow() == 0;
+        let op_code: OpCode = match self.echo_expressions && is_top_level {
+            true => OpCode::Print,
+            false => OpCode::Pop,
+        };
+        context
             .chunk
             .borrow_mut()
-            .write_code(OpCode::Pop.into(), self.previous.line);
+            .write_code(op_code.into(), self.previous.line);
     }
 
     fn variable_statement(&mut self) {
@@ -447,36 +613,8 @@ impl Compiler {
                         }
                         self.consume(TokenType::Semicolon, "Expect ';' after variable statement");
 
-                        let mut curr_variables = context.variables.borrow_mut();
-                        let curr_variable_map = curr_variables.get_mut(&curr_depth).unwrap();
-
-                        match curr_depth {
-                            0 => {
-                                let global_slot = curr_variable_map.len();
-                                let idx_option =
-                                    context.chunk.borrow_mut().add_variable(global_slot);
-                                match idx_option {
-                                    Ok(idx) => {
-                                        curr_variable_map
-                                            .insert(identifier_token.lexeme, global_slot);
-                                        context.chunk.borrow_mut().write_code(
-                                            OpCode::DefineGlobal.into(),
-                                            identifier_token.line,
-                                        );
-                                        context
-                                            .chunk
-                                            .borrow_mut()
-                                            .write_code(idx as u8, identifier_token.line);
-                                    }
-                                    Err(e) => self.throw_error(&identifier_token, &e),
-                                }
-                            }
-                            _ => {
-                                curr_variable_map
-                                    .insert(identifier_token.lexeme, *context.local_count.borrow());
-                                *context.local_count.borrow_mut() += 1;
-                            }
-                        }
+                        self.declare_variable(&identifier_token);
+                        self.define_variable(&identifier_token);
                     }
                 };
             }
@@ -529,7 +667,7 @@ impl Compiler {
     fn while_statement(&mut self) {
         let context: Rc<CompileContext> = self.curr_context();
 
-        let start_code_offset: usize = context.chunk.borrow().code_size() - 1;
+        let start_code_offset: usize = context.chunk.borrow().code_size();
 
         self.consume(TokenType::LeftParen, "Expect '(' after 'while'");
         self.parse_expression();
@@ -540,6 +678,7 @@ impl Compiler {
             .chunk
             .borrow_mut()
             .write_code(OpCode::Pop.into(), self.previous.line); // pop while expression
+        self.loop_begin(start_code_offset);
         self.statement();
         self.patch_back(OpCode::JumpBack, start_code_offset);
         self.patch_forward_end(jump_false_code_offset);
@@ -547,6 +686,7 @@ impl Compiler {
             .chunk
             .borrow_mut()
             .write_code(OpCode::Pop.into(), self.previous.line); // pop while expression
+        self.loop_end();
     }
 
     fn for_statement(&mut self) {
@@ -566,7 +706,7 @@ impl Compiler {
             _ => self.expression_statement(),
         }
 
-        let mut start_code_offset: usize = context.chunk.borrow().code_size() - 1;
+        let mut start_code_offset: usize = context.chunk.borrow().code_size();
 
         let mut jump_false_code_offset: Option<usize> = None;
         if !self.r#match(TokenType::Semicolon) {
@@ -583,7 +723,7 @@ impl Compiler {
         if !self.r#match(TokenType::RightParen) {
             let jump_code_offset: usize = self.patch_forward_begin(OpCode::Jump);
 
-            let increment_code_offset = context.chunk.borrow().code_size() - 1;
+            let increment_code_offset = context.chunk.borrow().code_size();
             self.parse_expression();
             context
                 .chunk
@@ -596,6 +736,7 @@ impl Compiler {
             self.patch_forward_end(jump_code_offset);
         }
 
+        self.loop_begin(start_code_offset);
         self.statement();
         self.patch_back(OpCode::JumpBack, start_code_offset);
 
@@ -606,183 +747,286 @@ impl Compiler {
                 .borrow_mut()
                 .write_code(OpCode::Pop.into(), self.previous.line); // pop for condition
         }
+        self.loop_end();
 
         self.scoop_end();
     }
 
-    fn function_statement(&mut self) {
-        if self.curr_context().function_name.borrow().len() != 0 {
-            self.throw_error(&self.previous, "function define only in top-level code");
+    fn loop_begin(&mut self, start_code_offset: usize) {
+        let context: Rc<CompileContext> = self.curr_context();
+        let depth: usize = *context.depth.borrow();
+        context.loops.borrow_mut().push(LoopContext {
+            start: start_code_offset,
+            depth,
+            breaks: Vec::new(),
+        });
+    }
+
+    fn loop_end(&mut self) {
+        let loop_context: LoopContext = self.curr_context().loops.borrow_mut().pop().unwrap();
+        for jump_code_offset in loop_context.breaks {
+            self.patch_forward_end(jump_code_offset);
         }
+    }
+
+    fn break_statement(&mut self) {
+        let context: Rc<CompileContext> = self.curr_context();
+        let depth: Option<usize> = context.loops.borrow().last().map(|l| l.depth);
+        match depth {
+            Some(depth) => {
+                self.consume(TokenType::Semicolon, "Expect ';' after 'break'");
+                self.discard_locals(depth);
+                let jump_code_offset: usize = self.patch_forward_begin(OpCode::Jump);
+                context
+                    .loops
+                    .borrow_mut()
+                    .last_mut()
+                    .unwrap()
+                    .breaks
+                    .push(jump_code_offset);
+            }
+            None => self.throw_error(&self.previous, "Can't use 'break' outside of a loop"),
+        }
+    }
 
+    fn continue_statement(&mut self) {
+        let context: Rc<CompileContext> = self.curr_context();
+        let target: Option<(usize, usize)> =
+            context.loops.borrow().last().map(|l| (l.start, l.depth));
+        match target {
+            Some((start_code_offset, depth)) => {
+                self.consume(TokenType::Semicolon, "Expect ';' after 'continue'");
+                self.discard_locals(depth);
+                self.patch_back(OpCode::JumpBack, start_code_offset);
+            }
+            None => self.throw_error(&self.previous, "Can't use 'continue' outside of a loop"),
+        }
+    }
+
+    fn function_statement(&mut self) {
         match self.r#match(TokenType::Identifier) {
             true => {
                 let identifier_token: Token = self.previous.clone();
+                if self.declare_variable(&identifier_token) {
+                    self.function(&identifier_token, FunctionType::Function);
+                    self.define_variable(&identifier_token);
+                }
+            }
+            false => self.throw_error(&self.current, "Expect function error"),
+        }
+    }
 
-                match {
-                    let context: Rc<CompileContext> = self.curr_context();
-                    let curr_depth = *context.depth.borrow();
-                    let curr_variables = context.variables.borrow();
-                    let curr_variable_map = curr_variables.get(&curr_depth).unwrap();
-                    curr_variable_map.contains_key(&identifier_token.lexeme)
-                } {
-                    true => {
-                        self.throw_error(&identifier_token, "Redefined identifier in curr space")
+    fn class_statement(&mut self) {
+        match self.r#match(TokenType::Identifier) {
+            true => {
+                let identifier_token: Token = self.previous.clone();
+                if !self.declare_variable(&identifier_token) {
+                    return;
+                }
+                self.emit_string_operand(OpCode::Class, &identifier_token);
+                self.define_variable(&identifier_token);
+
+                self.class_context_stack.push(ClassContext {
+                    has_superclass: false,
+                });
+                if self.r#match(TokenType::Less) {
+                    self.consume(TokenType::Identifier, "Expect superclass name");
+                    let superclass_token: Token = self.previous.clone();
+                    if superclass_token.lexeme == identifier_token.lexeme {
+                        self.throw_error(&superclass_token, "A class can't inherit from itself");
                     }
-                    false => {
-                        {
-                            let context: Rc<CompileContext> = self.curr_context();
-                            let curr_depth = *context.depth.borrow();
-                            let mut curr_variables = context.variables.borrow_mut();
-                            let curr_variable_map = curr_variables.get_mut(&curr_depth).unwrap();
-
-                            match curr_depth {
-                                0 => {
-                                    let global_slot = curr_variable_map.len();
-                                    curr_variable_map.insert(identifier_token.lexeme.clone(), global_slot);
+                    self.named_variable(superclass_token, false);
+
+                    // superclass stays on stack as local 'super'
+                    self.scoop_begin();
+                    let super_token = Token {
+                        r#type: TokenType::Super,
+                        lexeme: "super".to_string(),
+                        line: identifier_token.line,
+                        column: identifier_token.column,
+                    };
+                    self.declare_variable(&super_token);
+                    self.named_variable(identifier_token.clone(), false);
+                    self.curr_context()
+                        .chunk
+                        .borrow_mut()
+                        .write_code(OpCode::Inherit.into(), identifier_token.line);
+                    self.class_context_stack.last_mut().unwrap().has_superclass = true;
+                }
+
+                // keep class on stack while binding methods
+                self.named_variable(identifier_token.clone(), false);
+                self.consume(TokenType::LeftBrace, "Expect '{' before class body");
+                while self.current.r#type != TokenType::RightBrace
+                    && self.current.r#type != TokenType::Eof
+                {
+                    self.method();
+                }
+                self.consume(TokenType::RightBrace, "Expect '}' after class body");
+                self.curr_context()
+                    .chunk
+                    .borrow_mut()
+                    .write_code(OpCode::Pop.into(), self.previous.line);
+                if self.class_context_stack.pop().unwrap().has_superclass {
+                    self.scoop_end();
+                }
+            }
+            false => self.throw_error(&self.current, "Expect class name"),
+        }
+    }
+
+    fn method(&mut self) {
+        match self.r#match(TokenType::Identifier) {
+            true => {
+                let identifier_token: Token = self.previous.clone();
+                let function_type = match identifier_token.lexeme.as_str() {
+                    "init" => FunctionType::Initializer,
+                    _ => FunctionType::Method,
+                };
+                self.function(&identifier_token, function_type);
+                self.emit_string_operand(OpCode::Method, &identifier_token);
+            }
+            false => {
+                self.throw_error(&self.current, "Expect method name");
+                self.advance();
+            }
+        }
+    }
+
+    fn declare_variable(&mut self, identifier_token: &Token) -> bool {
+        let context: Rc<CompileContext> = self.curr_context();
+        let curr_depth = *context.depth.borrow();
+        let mut curr_variables = context.variables.borrow_mut();
+        let curr_variable_map = curr_variables.get_mut(&curr_depth).unwrap();
+        if curr_variable_map.contains_key(&identifier_token.lexeme) {
+            self.throw_error(identifier_token, "Redefined identifier in curr space");
+            return false;
+        }
+
+        match curr_depth {
+            0 => {
+                let global_slot = curr_variable_map.len();
+                curr_variable_map.insert(identifier_token.lexeme.clone(), global_slot);
+            }
+            _ => {
+                curr_variable_map.insert(
+                    identifier_token.lexeme.clone(),
+                    *context.local_count.borrow(),
+                );
+                *context.local_count.borrow_mut() += 1;
+            }
+        }
+        true
+    }
+
+    fn define_variable(&mut self, identifier_token: &Token) {
+        // locals stay on the stack in their slot, globals are bound by name
+        if *self.curr_context().depth.borrow() == 0 {
+            self.emit_string_operand(OpCode::DefineGlobal, identifier_token);
+        }
+    }
+
+    fn function(&mut self, identifier_token: &Token, function_type: FunctionType) {
+        self.push_context(function_type);
+
+        {
+            self.scoop_begin(); // no end scoop
+
+            let context: Rc<CompileContext> = self.curr_context();
+            context
+                .function_name
+                .replace(identifier_token.lexeme.clone());
+            if function_type == FunctionType::Method || function_type == FunctionType::Initializer {
+                context
+                    .variables
+                    .borrow_mut()
+                    .get_mut(&1)
+                    .unwrap()
+                    .insert("this".to_string(), 0);
+            }
+
+            self.consume(TokenType::LeftParen, "Expect '(' after function name");
+            if self.current.r#type != TokenType::RightParen {
+                let curr_depth = *context.depth.borrow();
+                let mut curr_variables = context.variables.borrow_mut();
+                let curr_variable_map = curr_variables.get_mut(&curr_depth).unwrap();
+
+                loop {
+                    *context.params_num.borrow_mut() += 1;
+                    // define local variable
+                    match self.r#match(TokenType::Identifier) {
+                        true => {
+                            match curr_variable_map.contains_key(&self.previous.lexeme) {
+                                true => {
+                                    self.throw_error(
+                                        identifier_token,
+                                        "Redefined param in curr function",
+                                    );
+                                    break;
                                 }
-                                _ => {
+                                false => {
                                     curr_variable_map.insert(
-                                        identifier_token.lexeme.clone(),
+                                        self.previous.lexeme.clone(),
                                         *context.local_count.borrow(),
                                     );
                                     *context.local_count.borrow_mut() += 1;
                                 }
-                            }
-                        };
-
-                        self.push_context();
-
-                        {
-                            self.scoop_begin(); // no end scoop
-
-                            let context: Rc<CompileContext> = self.curr_context();
-                            context
-                                .function_name
-                                .replace(identifier_token.lexeme.clone());
-
-                            self.consume(TokenType::LeftParen, "Expect '(' after function name");
-                            if self.current.r#type != TokenType::RightParen {
-                                let curr_depth = *context.depth.borrow();
-                                let mut curr_variables = context.variables.borrow_mut();
-                                let curr_variable_map =
-                                    curr_variables.get_mut(&curr_depth).unwrap();
-
-                                loop {
-                                    *context.params_num.borrow_mut() += 1;
-                                    // define local variable
-                                    match self.r#match(TokenType::Identifier) {
-                                        true => {
-                                            match curr_variable_map
-                                                .contains_key(&self.previous.lexeme)
-                                            {
-                                                true => {
-                                                    self.throw_error(
-                                                        &identifier_token,
-                                                        "Redefined param in curr function",
-                                                    );
-                                                    break;
-                                                }
-                                                false => {
-                                                    curr_variable_map.insert(
-                                                        self.previous.lexeme.clone(),
-                                                        *context.local_count.borrow(),
-                                                    );
-                                                    *context.local_count.borrow_mut() += 1;
-                                                }
-                                            };
-                                        }
-                                        false => {
-                                            self.throw_error(
-                                                &self.current,
-                                                "Expect function param error",
-                                            );
-                                            break;
-                                        }
-                                    }
-                                    if !self.r#match(TokenType::Comma) {
-                                        break;
-                                    }
-                                }
-                            }
-
-                            self.consume(TokenType::RightParen, "Expect ')' after parameters");
-
-                            self.consume(TokenType::LeftBrace, "Expect '{' before function body");
-                            self.block_statement();
-                        }
-
-                        let function: Function = self.compile_end();
-
-                        {
-                            // OP function push value;
-                            let context: Rc<CompileContext> = self.curr_context();
-                            let curr_depth = *context.depth.borrow();
-                            context
-                                .chunk
-                                .borrow_mut()
-                                .write_code(OpCode::Function.into(), identifier_token.line);
-                            let idx_option =
-                                context.chunk.borrow_mut().add_function(Rc::new(function));
-                            match idx_option {
-                                Ok(idx) => context
-                                    .chunk
-                                    .borrow_mut()
-                                    .write_code(idx as u8, identifier_token.line),
-                                Err(e) => self.throw_error(&identifier_token, &e),
                             };
-
-                            // OP define function as variable
-                            if curr_depth == 0 {
-                                let global_slot = context
-                                    .variables
-                                    .borrow()
-                                    .get(&0)
-                                    .unwrap()
-                                    .get(&identifier_token.lexeme)
-                                    .unwrap()
-                                    .clone();
-                                let idx_option =
-                                    context.chunk.borrow_mut().add_variable(global_slot);
-                                match idx_option {
-                                    Ok(idx) => {
-                                        context.chunk.borrow_mut().write_code(
-                                            OpCode::DefineGlobal.into(),
-                                            identifier_token.line,
-                                        );
-                                        context
-                                            .chunk
-                                            .borrow_mut()
-                                            .write_code(idx as u8, identifier_token.line);
-                                    }
-                                    Err(e) => self.throw_error(&identifier_token, &e),
-                                }
-                            }
+                        }
+                        false => {
+                            self.throw_error(&self.current, "Expect function param error");
+                            break;
                         }
                     }
+                    if !self.r#match(TokenType::Comma) {
+                        break;
+                    }
                 }
             }
-            false => self.throw_error(&self.current, "Expect function error"),
+
+            self.consume(TokenType::RightParen, "Expect ')' after parameters");
+
+            self.consume(TokenType::LeftBrace, "Expect '{' before function body");
+            self.block_statement();
+        }
+
+        let (function, upvalues) = self.compile_end();
+
+        // OP closure push value;
+        let context: Rc<CompileContext> = self.curr_context();
+        let idx_option = context.chunk.borrow_mut().add_function(Rc::new(function));
+        match idx_option {
+            Ok(idx) => context.chunk.borrow_mut().write_index_code(
+                OpCode::Closure,
+                idx,
+                identifier_token.line,
+            ),
+            Err(e) => self.throw_error(identifier_token, &e),
+        };
+        for upvalue in upvalues {
+            context
+                .chunk
+                .borrow_mut()
+                .write_code(upvalue.is_local as u8, identifier_token.line);
+            context
+                .chunk
+                .borrow_mut()
+                .write_code(upvalue.index as u8, identifier_token.line);
         }
     }
 
     fn return_statement(&mut self) {
-        if self.curr_context().function_name.borrow().len() == 0 {
+        let function_type: FunctionType = self.curr_context().function_type;
+        if function_type == FunctionType::Script {
             self.throw_error(&self.previous, "Can't return from top-level code");
         }
 
         match self.r#match(TokenType::Semicolon) {
-            true => {
-                self.curr_context()
-                    .chunk
-                    .borrow_mut()
-                    .write_code(OpCode::Nil.into(), self.previous.line);
-                self.curr_context()
-                    .chunk
-                    .borrow_mut()
-                    .write_code(OpCode::Return.into(), self.previous.line);
-            }
+            true => self.emit_return(),
             false => {
+                if function_type == FunctionType::Initializer {
+                    self.throw_error(&self.previous, "Can't return a value from an initializer");
+                }
                 self.parse_expression();
                 self.consume(TokenType::Semicolon, "Expect ';' after return value.");
                 self.curr_context()
@@ -793,6 +1037,45 @@ impl Compiler {
         }
     }
 
+    fn emit_return(&mut self) {
+        let context: Rc<CompileContext> = self.curr_context();
+        match context.function_type {
+            FunctionType::Initializer => {
+                // initializer always returns 'this'
+                let idx_option = context.chunk.borrow_mut().add_variable(0);
+                match idx_option {
+                    Ok(idx) => context.chunk.borrow_mut().write_index_code(
+                        OpCode::GetLocal,
+                        idx,
+                        self.previous.line,
+                    ),
+                    Err(e) => self.throw_error(&self.previous, &e),
+                }
+            }
+            _ => context
+                .chunk
+                .borrow_mut()
+                .write_code(OpCode::Nil.into(), self.previous.line),
+        }
+        context
+            .chunk
+            .borrow_mut()
+            .write_code(OpCode::Return.into(), self.previous.line);
+    }
+
+    fn emit_string_operand(&mut self, op_code: OpCode, token: &Token) {
+        let context: Rc<CompileContext> = self.curr_context();
+        let string: Rc<str> = self.interner.borrow_mut().intern(&token.lexeme);
+        let idx_option = context.chunk.borrow_mut().add_string(string);
+        match idx_option {
+            Ok(idx) => context
+                .chunk
+                .borrow_mut()
+                .write_index_code(op_code, idx, token.line),
+            Err(e) => self.throw_error(token, &e),
+        }
+    }
+
     fn scoop_begin(&mut self) {
         let context: Rc<CompileContext> = self.curr_context();
         *context.depth.borrow_mut() += 1;
@@ -806,18 +1089,54 @@ impl Compiler {
     fn scoop_end(&mut self) {
         let context: Rc<CompileContext> = self.curr_context();
         let depth = *context.depth.borrow();
-        let block_variables_len: usize = context.variables.borrow().get(&depth).unwrap().len();
-        for _ in 0..block_variables_len {
+        let mut block_slots: Vec<usize> = context
+            .variables
+            .borrow()
+            .get(&depth)
+            .unwrap()
+            .values()
+            .copied()
+            .collect();
+        block_slots.sort_unstable_by(|a, b| b.cmp(a));
+        for slot in block_slots.iter() {
+            let op_code: OpCode = match context.captured.borrow_mut().remove(slot) {
+                true => OpCode::CloseUpvalue,
+                false => OpCode::Pop,
+            };
             context
                 .chunk
                 .borrow_mut()
-                .write_code(OpCode::Pop.into(), self.previous.line);
+                .write_code(op_code.into(), self.previous.line);
         }
-        *context.local_count.borrow_mut() -= block_variables_len;
+        *context.local_count.borrow_mut() -= block_slots.len();
         context.variables.borrow_mut().remove(&depth);
         *context.depth.borrow_mut() -= 1;
     }
 
+    // pop the locals of every scope deeper than depth before jumping out of
+    // them, the scopes themselves stay open for the code that follows
+    fn discard_locals(&mut self, depth: usize) {
+        let context: Rc<CompileContext> = self.curr_context();
+        let mut slots: Vec<usize> = context
+            .variables
+            .borrow()
+            .iter()
+            .filter(|(d, _)| **d > depth)
+            .flat_map(|(_, variables)| variables.values().copied())
+            .collect();
+        slots.sort_unstable_by(|a, b| b.cmp(a));
+        for slot in slots.iter() {
+            let op_code: OpCode = match context.captured.borrow().contains(slot) {
+                true => OpCode::CloseUpvalue,
+                false => OpCode::Pop,
+            };
+            context
+                .chunk
+                .borrow_mut()
+                .write_code(op_code.into(), self.previous.line);
+        }
+    }
+
     fn patch_forward_begin(&mut self, jump_code: OpCode) -> usize {
         let context: Rc<CompileContext> = self.curr_context();
         let jump_code_offset: usize = context.chunk.borrow().code_size();
@@ -838,9 +1157,15 @@ impl Compiler {
 
     fn patch_forward_end(&mut self, jump_code_offset: usize) {
         let context: Rc<CompileContext> = self.curr_context();
-        let jump_count: usize = context.chunk.borrow().code_size() - jump_code_offset - 3;
+        let target_code_offset: usize = context.chunk.borrow().code_size();
+        let jump_count: usize = target_code_offset - jump_code_offset - 3;
         if jump_count > u16::MAX as usize {
-            self.throw_error(&self.previous, "Too much code to jump over");
+            // widened once the function is complete
+            context
+                .wide_jumps
+                .borrow_mut()
+                .insert(jump_code_offset, target_code_offset);
+            return;
         }
         context
             .chunk
@@ -854,9 +1179,15 @@ impl Compiler {
 
     fn patch_back(&mut self, jump_code: OpCode, start_code_offset: usize) {
         let context: Rc<CompileContext> = self.curr_context();
-        let jump_count: usize = context.chunk.borrow_mut().code_size() - start_code_offset + 2;
+        let jump_code_offset: usize = context.chunk.borrow().code_size();
+        let mut jump_count: usize = jump_code_offset - start_code_offset + 3;
         if jump_count > u16::MAX as usize {
-            self.throw_error(&self.previous, "Too much code to jump over");
+            // widened once the function is complete
+            context
+                .wide_jumps
+                .borrow_mut()
+                .insert(jump_code_offset, start_code_offset);
+            jump_count = u16::MAX as usize;
         }
         context
             .chunk
@@ -886,18 +1217,15 @@ impl Compiler {
 
     fn parse_number(&mut self) {
         let context: Rc<CompileContext> = self.curr_context();
-        context
-            .chunk
-            .borrow_mut()
-            .write_code(OpCode::Number.into(), self.previous.line);
         match self.previous.lexeme.parse::<f64>() {
             Ok(number) => {
                 let idx_option = context.chunk.borrow_mut().add_number(number);
                 match idx_option {
-                    Ok(idx) => context
-                        .chunk
-                        .borrow_mut()
-                        .write_code(idx as u8, self.previous.line),
+                    Ok(idx) => context.chunk.borrow_mut().write_index_code(
+                        OpCode::Number,
+                        idx,
+                        self.previous.line,
+                    ),
                     Err(e) => self.throw_error(&self.previous, &e),
                 }
             }
@@ -907,24 +1235,22 @@ impl Compiler {
 
     fn parse_string(&mut self) {
         let context: Rc<CompileContext> = self.curr_context();
-        context
-            .chunk
-            .borrow_mut()
-            .write_code(OpCode::String.into(), self.previous.line);
         let string_len: usize = self.previous.lexeme.len();
         match string_len >= 2 {
             true => {
-                let string: String = if string_len > 2 {
-                    self.previous.lexeme[1..(string_len - 1)].to_string()
-                } else {
-                    "".to_string()
+                // the scanner already rejected bad escapes
+                let value: String = match self.previous.lexeme.strip_prefix('r') {
+                    Some(raw) => raw[1..(raw.len() - 1)].to_string(),
+                    None => unescape(&self.previous.lexeme[1..(string_len - 1)]).unwrap(),
                 };
+                let string: Rc<str> = self.interner.borrow_mut().intern(&value);
                 let idx_option = context.chunk.borrow_mut().add_string(string);
                 match idx_option {
-                    Ok(idx) => context
-                        .chunk
-                        .borrow_mut()
-                        .write_code(idx as u8, self.previous.line),
+                    Ok(idx) => context.chunk.borrow_mut().write_index_code(
+                        OpCode::String,
+                        idx,
+                        self.previous.line,
+                    ),
                     Err(e) => self.throw_error(&self.previous, &e),
                 }
             }
@@ -951,79 +1277,175 @@ impl Compiler {
         }
     }
 
+    fn resolve_local(context: &CompileContext, name: &str) -> Option<usize> {
+        let mut curr_depth = *context.depth.borrow();
+        let curr_variables = context.variables.borrow();
+        loop {
+            if curr_depth < 1 {
+                break Option::None;
+            }
+            let variable_map = curr_variables.get(&curr_depth).unwrap();
+            match variable_map.get(name) {
+                Some(v) => break Option::Some(*v),
+                None => curr_depth -= 1,
+            }
+        }
+    }
+
+    fn resolve_upvalue(&self, context_index: usize, name: &str) -> Result<Option<usize>, String> {
+        if context_index == 0 {
+            return Ok(None);
+        }
+        let enclosing: Rc<CompileContext> = self.compile_context_stack[context_index - 1].clone();
+        if let Some(local_slot) = Self::resolve_local(&enclosing, name) {
+            enclosing.captured.borrow_mut().insert(local_slot);
+            return self.add_upvalue(context_index, true, local_slot).map(Some);
+        }
+        match self.resolve_upvalue(context_index - 1, name)? {
+            Some(upvalue_slot) => self
+                .add_upvalue(context_index, false, upvalue_slot)
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn add_upvalue(
+        &self,
+        context_index: usize,
+        is_local: bool,
+        index: usize,
+    ) -> Result<usize, String> {
+        let context: &CompileContext = &self.compile_context_stack[context_index];
+        let upvalue = UpvalueInfo { is_local, index };
+        let mut upvalues = context.upvalues.borrow_mut();
+        if let Some(slot) = upvalues.iter().position(|u| *u == upvalue) {
+            return Ok(slot);
+        }
+        // capture operands stay one byte even though locals can go past 255
+        if index > u8::MAX as usize {
+            return Err("Can't capture local slot past 255".to_string());
+        }
+        if upvalues.len() > u8::MAX as usize {
+            return Err("Too many closure variables in function".to_string());
+        }
+        upvalues.push(upvalue);
+        Ok(upvalues.len() - 1)
+    }
+
     fn parse_variable(&mut self) {
-        let context: Rc<CompileContext> = self.curr_context();
-        let root_context: Rc<CompileContext> = self.root_context();
         let variable_token = self.previous.clone();
+        self.named_variable(variable_token, true);
+    }
 
-        if let Some(local_slot) = {
-            let mut curr_depth = *context.depth.borrow();
-            let curr_variables = context.variables.borrow();
-            let variable_slot: Option<usize> = loop {
-                if curr_depth < 1 {
-                    break Option::None;
-                }
-                let variable_map = curr_variables.get(&curr_depth).unwrap();
-                match variable_map.get(&variable_token.lexeme) {
-                    Some(v) => break Option::Some(*v),
-                    None => curr_depth -= 1,
+    fn parse_this(&mut self) {
+        if self.class_context_stack.is_empty() {
+            self.throw_error(&self.previous, "Can't use 'this' outside of a class");
+            return;
+        }
+        let this_token = self.previous.clone();
+        self.named_variable(this_token, false);
+    }
+
+    fn parse_super(&mut self) {
+        let super_token = self.previous.clone();
+        match self.class_context_stack.last() {
+            None => self.throw_error(&super_token, "Can't use 'super' outside of a class"),
+            Some(class_context) if !class_context.has_superclass => self.throw_error(
+                &super_token,
+                "Can't use 'super' in a class with no superclass",
+            ),
+            _ => {}
+        }
+        self.consume(TokenType::Dot, "Expect '.' after 'super'");
+        self.consume(TokenType::Identifier, "Expect superclass method name");
+        let method_token = self.previous.clone();
+
+        let this_token = Token {
+            r#type: TokenType::This,
+            lexeme: "this".to_string(),
+            line: super_token.line,
+            column: super_token.column,
+        };
+        self.named_variable(this_token, false);
+        self.named_variable(super_token, false);
+        self.emit_string_operand(OpCode::GetSuper, &method_token);
+    }
+
+    fn parse_dot(&mut self) {
+        self.consume(TokenType::Identifier, "Expect property name after '.'");
+        let property_token = self.previous.clone();
+        match self.r#match(TokenType::Equal) {
+            true => {
+                self.parse_expression();
+                self.emit_string_operand(OpCode::SetProperty, &property_token);
+            }
+            false => self.emit_string_operand(OpCode::GetProperty, &property_token),
+        }
+    }
+
+    fn named_variable(&mut self, variable_token: Token, can_assign: bool) {
+        let context: Rc<CompileContext> = self.curr_context();
+
+        let local_slot: Option<usize> = Self::resolve_local(&context, &variable_token.lexeme);
+        let upvalue_slot: Option<usize> = match local_slot {
+            Some(_) => None,
+            None => {
+                let context_index: usize = self.compile_context_stack.len() - 1;
+                match self.resolve_upvalue(context_index, &variable_token.lexeme) {
+                    Ok(slot) => slot,
+                    Err(e) => {
+                        self.throw_error(&variable_token, &e);
+                        return;
+                    }
                 }
-            };
-            variable_slot
-        } {
-            match self.r#match(TokenType::Equal) {
+            }
+        };
+
+        if let Some(local_slot) = local_slot {
+            let op_code: OpCode = match can_assign && self.r#match(TokenType::Equal) {
                 true => {
                     self.parse_expression();
+                    OpCode::SetLocal
+                }
+                false => OpCode::GetLocal,
+            };
+            let idx_option = context.chunk.borrow_mut().add_variable(local_slot);
+            match idx_option {
+                Ok(idx) => {
                     context
                         .chunk
                         .borrow_mut()
-                        .write_code(OpCode::SetLocal.into(), variable_token.line);
+                        .write_index_code(op_code, idx, variable_token.line)
                 }
-                false => context
-                    .chunk
-                    .borrow_mut()
-                    .write_code(OpCode::GetLocal.into(), variable_token.line),
-            }
-            let idx_option = context.chunk.borrow_mut().add_variable(local_slot);
-            match idx_option {
-                Ok(idx) => context
-                    .chunk
-                    .borrow_mut()
-                    .write_code(idx as u8, variable_token.line),
                 Err(e) => self.throw_error(&variable_token, &e),
             }
-        } else if let Some(global_slot) = {
-            let root_variables = root_context.variables.borrow();
-            let global_variable_slot = root_variables
-                .get(&0)
-                .unwrap()
-                .get(&variable_token.lexeme)
-                .cloned();
-            global_variable_slot
-        } {
-            match self.r#match(TokenType::Equal) {
+        } else if let Some(upvalue_slot) = upvalue_slot {
+            match can_assign && self.r#match(TokenType::Equal) {
                 true => {
                     self.parse_expression();
                     context
                         .chunk
                         .borrow_mut()
-                        .write_code(OpCode::SetGlobal.into(), variable_token.line);
+                        .write_code(OpCode::SetUpvalue.into(), variable_token.line);
                 }
                 false => context
                     .chunk
                     .borrow_mut()
-                    .write_code(OpCode::GetGlobal.into(), variable_token.line),
-            }
-            let idx_option = context.chunk.borrow_mut().add_variable(global_slot);
-            match idx_option {
-                Ok(idx) => context
-                    .chunk
-                    .borrow_mut()
-                    .write_code(idx as u8, variable_token.line),
-                Err(e) => self.throw_error(&variable_token, &e),
+                    .write_code(OpCode::GetUpvalue.into(), variable_token.line),
             }
+            context
+                .chunk
+                .borrow_mut()
+                .write_code(upvalue_slot as u8, variable_token.line);
         } else {
-            self.throw_error(&variable_token, "Undefined variable Error")
+            // unresolved names are late-bound globals
+            match can_assign && self.r#match(TokenType::Equal) {
+                true => {
+                    self.parse_expression();
+                    self.emit_string_operand(OpCode::SetGlobal, &variable_token);
+                }
+                false => self.emit_string_operand(OpCode::GetGlobal, &variable_token),
+            }
         }
     }
 
@@ -1041,6 +1463,10 @@ impl Compiler {
                 .chunk
                 .borrow_mut()
                 .write_code(OpCode::Not.into(), unary_token.line),
+            TokenType::Tilde => context
+                .chunk
+                .borrow_mut()
+                .write_code(OpCode::BitNot.into(), unary_token.line),
             _ => self.throw_error(&unary_token, "Expect unary Error"),
         }
     }
@@ -1048,11 +1474,13 @@ impl Compiler {
     fn parse_binary(&mut self) {
         let context: Rc<CompileContext> = self.curr_context();
         let binary_token = self.previous.clone();
-        self.parse_precedence(
-            PARSE_RULES[Into::<usize>::into(binary_token.r#type.clone())]
-                .precedence
-                .promote(),
-        );
+        let precedence: Precedence =
+            PARSE_RULES[Into::<usize>::into(binary_token.r#type.clone())].precedence;
+        // '**' is right-associative, its right operand may hold another '**'
+        match binary_token.r#type {
+            TokenType::StarStar => self.parse_precedence(precedence),
+            _ => self.parse_precedence(precedence.promote()),
+        }
 
         match binary_token.r#type {
             TokenType::Plus => context
@@ -1071,6 +1499,38 @@ impl Compiler {
                 .chunk
                 .borrow_mut()
                 .write_code(OpCode::Divide.into(), binary_token.line),
+            TokenType::Percent => context
+                .chunk
+                .borrow_mut()
+                .write_code(OpCode::Modulo.into(), binary_token.line),
+            TokenType::StarStar => context
+                .chunk
+                .borrow_mut()
+                .write_code(OpCode::Power.into(), binary_token.line),
+            TokenType::SlashSlash => context
+                .chunk
+                .borrow_mut()
+                .write_code(OpCode::FloorDivide.into(), binary_token.line),
+            TokenType::Ampersand => context
+                .chunk
+                .borrow_mut()
+                .write_code(OpCode::BitAnd.into(), binary_token.line),
+            TokenType::Pipe => context
+                .chunk
+                .borrow_mut()
+                .write_code(OpCode::BitOr.into(), binary_token.line),
+            TokenType::Caret => context
+                .chunk
+                .borrow_mut()
+                .write_code(OpCode::BitXor.into(), binary_token.line),
+            TokenType::LessLess => context
+                .chunk
+                .borrow_mut()
+                .write_code(OpCode::ShiftLeft.into(), binary_token.line),
+            TokenType::GreaterGreater => context
+                .chunk
+                .borrow_mut()
+                .write_code(OpCode::ShiftRight.into(), binary_token.line),
             TokenType::BangEqual => {
                 context
                     .chunk
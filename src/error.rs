@@ -0,0 +1,57 @@
+#[derive(Debug)]
+pub struct CompileError {
+    pub line: u32,
+    pub column: u32,
+    pub lexeme: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for CompileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.lexeme.len() {
+            0 => write!(
+                f,
+                "[line {}:{}] Error : {}",
+                self.line, self.column, self.message
+            ),
+            _ => write!(
+                f,
+                "[line {}:{}] Error at '{}' : {}",
+                self.line, self.column, self.lexeme, self.message
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+#[derive(Debug)]
+pub struct TraceFrame {
+    pub function: String,
+    pub line: u32,
+}
+
+impl std::fmt::Display for TraceFrame {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "[line {}] in {}", self.line, self.function)
+    }
+}
+
+#[derive(Debug)]
+pub struct RuntimeError {
+    pub message: String,
+    // innermost frame first
+    pub trace: Vec<TraceFrame>,
+}
+
+impl std::fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.message)?;
+        for frame in &self.trace {
+            write!(f, "\n{}", frame)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RuntimeError {}
@@ -1,9 +1,14 @@
 use crate::chunk::*;
+use crate::value::Value;
+use crate::vm::VM;
+use std::cell::RefCell;
 use std::rc::Rc;
 
+#[derive(Clone)]
 pub struct Function {
     pub name: String,
     pub params_num: usize,
+    pub upvalue_count: usize,
     pub chunk: Rc<Chunk>,
 }
 
@@ -16,8 +21,57 @@ impl ToString for Function {
     }
 }
 
+impl std::fmt::Debug for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.to_string())
+    }
+}
+
 impl Function {
     pub fn disassemble(&self) {
         self.chunk.disassemble(&self.to_string());
     }
 }
+
+pub enum Upvalue {
+    // stack slot of the captured variable
+    Open(usize),
+    Closed(Value),
+}
+
+pub struct Closure {
+    pub function: Rc<Function>,
+    pub upvalues: Vec<Rc<RefCell<Upvalue>>>,
+}
+
+impl ToString for Closure {
+    fn to_string(&self) -> String {
+        self.function.to_string()
+    }
+}
+
+impl std::fmt::Debug for Closure {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.to_string())
+    }
+}
+
+pub type NativeFn = fn(&mut VM, &[Value]) -> Result<Value, String>;
+
+pub struct Native {
+    pub name: String,
+    pub arity: usize,
+    pub function: NativeFn,
+}
+
+impl ToString for Native {
+    fn to_string(&self) -> String {
+        format!("<native fn {}>", self.name)
+    }
+}
+
+impl std::fmt::Debug for Native {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.to_string())
+    }
+}
@@ -0,0 +1,237 @@
+use crate::class::{BoundMethod, Class, Instance};
+use crate::function::{Closure, Upvalue};
+use crate::value::Value;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::{Rc, Weak};
+
+const GC_HEAP_MIN: usize = 1024;
+const GC_HEAP_GROW: usize = 2;
+
+// objects that can take part in a reference cycle
+#[derive(Clone)]
+pub enum Object {
+    Closure(Rc<Closure>),
+    Class(Rc<Class>),
+    Instance(Rc<Instance>),
+    BoundMethod(Rc<BoundMethod>),
+    Upvalue(Rc<RefCell<Upvalue>>),
+}
+
+impl Object {
+    pub fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Closure(c) => Some(Self::Closure(c.clone())),
+            Value::Class(c) => Some(Self::Class(c.clone())),
+            Value::Instance(i) => Some(Self::Instance(i.clone())),
+            Value::BoundMethod(b) => Some(Self::BoundMethod(b.clone())),
+            _ => None,
+        }
+    }
+
+    fn address(&self) -> usize {
+        match self {
+            Self::Closure(c) => Rc::as_ptr(c) as *const () as usize,
+            Self::Class(c) => Rc::as_ptr(c) as *const () as usize,
+            Self::Instance(i) => Rc::as_ptr(i) as *const () as usize,
+            Self::BoundMethod(b) => Rc::as_ptr(b) as *const () as usize,
+            Self::Upvalue(u) => Rc::as_ptr(u) as *const () as usize,
+        }
+    }
+
+    fn strong_count(&self) -> usize {
+        match self {
+            Self::Closure(c) => Rc::strong_count(c),
+            Self::Class(c) => Rc::strong_count(c),
+            Self::Instance(i) => Rc::strong_count(i),
+            Self::BoundMethod(b) => Rc::strong_count(b),
+            Self::Upvalue(u) => Rc::strong_count(u),
+        }
+    }
+
+    fn downgrade(&self) -> WeakObject {
+        match self {
+            Self::Closure(c) => WeakObject::Closure(Rc::downgrade(c)),
+            Self::Class(c) => WeakObject::Class(Rc::downgrade(c)),
+            Self::Instance(i) => WeakObject::Instance(Rc::downgrade(i)),
+            Self::BoundMethod(b) => WeakObject::BoundMethod(Rc::downgrade(b)),
+            Self::Upvalue(u) => WeakObject::Upvalue(Rc::downgrade(u)),
+        }
+    }
+
+    fn children(&self) -> Vec<Object> {
+        match self {
+            Self::Closure(c) => c
+                .upvalues
+                .iter()
+                .map(|u| Self::Upvalue(u.clone()))
+                .collect(),
+            Self::Class(c) => c
+                .methods
+                .borrow()
+                .values()
+                .map(|m| Self::Closure(m.clone()))
+                .collect(),
+            Self::Instance(i) => {
+                let mut children: Vec<Object> = vec![Self::Class(i.class.clone())];
+                children.extend(i.fields.borrow().values().filter_map(Self::from_value));
+                children
+            }
+            Self::BoundMethod(b) => {
+                let mut children: Vec<Object> = vec![Self::Closure(b.method.clone())];
+                children.extend(Self::from_value(&b.receiver));
+                children
+            }
+            Self::Upvalue(u) => match &*u.borrow() {
+                Upvalue::Open(_) => Vec::new(),
+                Upvalue::Closed(value) => Self::from_value(value).into_iter().collect(),
+            },
+        }
+    }
+
+    // drop outgoing references, every cycle passes through one of these cells
+    fn clear(&self) {
+        match self {
+            Self::Class(c) => c.methods.borrow_mut().clear(),
+            Self::Instance(i) => i.fields.borrow_mut().clear(),
+            Self::Upvalue(u) => {
+                u.replace(Upvalue::Closed(Value::Nil));
+            }
+            Self::Closure(_) | Self::BoundMethod(_) => {}
+        }
+    }
+}
+
+enum WeakObject {
+    Closure(Weak<Closure>),
+    Class(Weak<Class>),
+    Instance(Weak<Instance>),
+    BoundMethod(Weak<BoundMethod>),
+    Upvalue(Weak<RefCell<Upvalue>>),
+}
+
+impl WeakObject {
+    fn upgrade(&self) -> Option<Object> {
+        match self {
+            Self::Closure(c) => c.upgrade().map(Object::Closure),
+            Self::Class(c) => c.upgrade().map(Object::Class),
+            Self::Instance(i) => i.upgrade().map(Object::Instance),
+            Self::BoundMethod(b) => b.upgrade().map(Object::BoundMethod),
+            Self::Upvalue(u) => u.upgrade().map(Object::Upvalue),
+        }
+    }
+
+    fn is_alive(&self) -> bool {
+        match self {
+            Self::Closure(c) => c.strong_count() > 0,
+            Self::Class(c) => c.strong_count() > 0,
+            Self::Instance(i) => i.strong_count() > 0,
+            Self::BoundMethod(b) => b.strong_count() > 0,
+            Self::Upvalue(u) => u.strong_count() > 0,
+        }
+    }
+}
+
+// reference counting frees acyclic garbage; the heap only has to find cycles
+pub struct Heap {
+    objects: Vec<WeakObject>,
+    allocated: usize,
+    next_gc: usize,
+    stress: bool,
+    log: bool,
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Heap {
+    pub fn new() -> Self {
+        Self {
+            objects: Vec::<WeakObject>::new(),
+            allocated: 0,
+            next_gc: GC_HEAP_MIN,
+            stress: false,
+            log: false,
+        }
+    }
+
+    pub fn set_stress(&mut self, stress: bool) {
+        self.stress = stress;
+    }
+
+    pub fn set_log(&mut self, log: bool) {
+        self.log = log;
+    }
+
+    pub fn is_log(&self) -> bool {
+        self.log
+    }
+
+    pub fn track(&mut self, object: &Object) {
+        self.objects.push(object.downgrade());
+        self.allocated += 1;
+    }
+
+    pub fn should_collect(&self) -> bool {
+        self.stress || self.allocated >= self.next_gc
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.iter().filter(|o| o.is_alive()).count()
+    }
+
+    // mark from the roots and from anything referenced outside the heap
+    // (host handles, values held by rust code), then break unmarked cycles
+    pub fn collect<'a>(
+        &mut self,
+        values: impl Iterator<Item = &'a Value>,
+        closures: impl Iterator<Item = &'a Rc<Closure>>,
+        upvalues: impl Iterator<Item = &'a Rc<RefCell<Upvalue>>>,
+    ) -> usize {
+        let mut seen: HashSet<usize> = HashSet::new();
+        self.objects.retain(|o| match o.upgrade() {
+            Some(object) => seen.insert(object.address()),
+            None => false,
+        });
+        let before: usize = self.objects.len();
+        let live: Vec<Object> = self.objects.iter().filter_map(|o| o.upgrade()).collect();
+
+        let mut internal: HashMap<usize, usize> = HashMap::new();
+        for object in live.iter() {
+            for child in object.children() {
+                *internal.entry(child.address()).or_insert(0) += 1;
+            }
+        }
+        let mut gray: Vec<Object> = live
+            .iter()
+            .filter(|o| o.strong_count() - 1 > internal.get(&o.address()).cloned().unwrap_or(0))
+            .cloned()
+            .collect();
+        gray.extend(values.filter_map(Object::from_value));
+        gray.extend(closures.map(|c| Object::Closure(c.clone())));
+        gray.extend(upvalues.map(|u| Object::Upvalue(u.clone())));
+
+        let mut marked: HashSet<usize> = HashSet::new();
+        while let Some(object) = gray.pop() {
+            if marked.insert(object.address()) {
+                gray.extend(object.children());
+            }
+        }
+
+        for object in live.iter() {
+            if !marked.contains(&object.address()) {
+                object.clear();
+            }
+        }
+        drop(live);
+        self.objects.retain(|o| o.is_alive());
+
+        let freed: usize = before - self.objects.len();
+        self.allocated = 0;
+        self.next_gc = GC_HEAP_MIN.max(self.objects.len() * GC_HEAP_GROW);
+        freed
+    }
+}
@@ -0,0 +1,50 @@
+use std::collections::HashSet;
+use std::rc::Rc;
+
+// one shared copy of every string, so equal strings are the same pointer
+pub struct Interner {
+    strings: HashSet<Rc<str>>,
+}
+
+impl Default for Interner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Self {
+            strings: HashSet::<Rc<str>>::new(),
+        }
+    }
+
+    pub fn intern(&mut self, string: &str) -> Rc<str> {
+        match self.strings.get(string) {
+            Some(interned) => interned.clone(),
+            None => {
+                let interned: Rc<str> = Rc::from(string);
+                self.strings.insert(interned.clone());
+                interned
+            }
+        }
+    }
+
+    // drop strings nothing but the table refers to
+    pub fn prune(&mut self) -> usize {
+        let before: usize = self.strings.len();
+        self.strings.retain(|string| Rc::strong_count(string) > 1);
+        before - self.strings.len()
+    }
+
+    // keeps the given allocation when the string is new
+    pub fn intern_rc(&mut self, string: Rc<str>) -> Rc<str> {
+        match self.strings.get(&string) {
+            Some(interned) => interned.clone(),
+            None => {
+                self.strings.insert(string.clone());
+                string
+            }
+        }
+    }
+}
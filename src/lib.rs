@@ -0,0 +1,39 @@
+//! lox scripting language interpreter: compile source with `Compiler`,
+//! run it on a `VM`, and call script functions back from Rust.
+
+// house style: ToString impls, match on bool, explicit loops over peeked chars
+#![allow(
+    clippy::to_string_trait_impl,
+    clippy::to_string_in_format_args,
+    clippy::eq_op,
+    clippy::blocks_in_conditions,
+    clippy::len_zero,
+    clippy::redundant_field_names,
+    clippy::collapsible_match,
+    clippy::derivable_impls,
+    clippy::while_let_loop,
+    clippy::needless_return,
+    clippy::clone_on_copy
+)]
+
+mod bytecode;
+mod chunk;
+mod class;
+mod compiler;
+mod error;
+mod function;
+mod gc;
+mod interner;
+mod repl;
+mod scanner;
+mod token;
+mod value;
+mod verifier;
+mod vm;
+
+pub use compiler::Compiler;
+pub use error::{CompileError, RuntimeError, TraceFrame};
+pub use function::{Function, NativeFn};
+pub use repl::Session;
+pub use value::Value;
+pub use vm::{InterpretResult, FRAMES_MAX, STACK_MAX, VM};
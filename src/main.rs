@@ -1,29 +1,39 @@
-mod chunk;
-mod compiler;
-mod function;
-mod scanner;
-mod token;
-mod value;
-mod vm;
+use rlox::{Compiler, InterpretResult, Session, VM};
 
-use vm::*;
+fn report(result: InterpretResult) {
+    match result {
+        InterpretResult::Success => {}
+        _ => eprintln!("{}", result.to_string()),
+    }
+}
 
-fn interpret(byte_stream: &String) {
+fn interpret(byte_stream: &str) {
     let mut vm = VM::new();
-    vm.interpret_source(byte_stream);
+    report(vm.interpret_source(byte_stream));
     // let mut compiler: Compiler = Compiler::new();
     // let _ = compiler.compile(byte_stream);
 }
 
 fn repl() {
+    let mut session = Session::new();
     let mut input = String::new();
     loop {
-        print!("> ");
+        match input.is_empty() {
+            true => print!("> "),
+            false => print!("... "),
+        }
         let _ = std::io::Write::flush(&mut std::io::stdout());
         match std::io::stdin().read_line(&mut input) {
+            Ok(0) => {
+                println!();
+                break;
+            }
             Ok(_) => {
+                if Session::is_incomplete(&input) {
+                    continue;
+                }
                 let line_stream = input.trim().to_string();
-                interpret(&line_stream);
+                report(session.eval(&line_stream));
                 input.clear();
             }
             Err(_) => {
@@ -34,20 +44,41 @@ fn repl() {
     }
 }
 
-fn run_file(file_path: &String) {
+fn run_file(file_path: &str) {
     let path: std::path::PathBuf = std::path::PathBuf::from(file_path);
     let data: Vec<u8> = std::fs::read(path).unwrap();
     let byte_stream: String = String::from_utf8(data).unwrap();
     interpret(&byte_stream);
 }
 
+fn compile_file(file_path: &str, output_path: &str) {
+    let byte_stream: String = std::fs::read_to_string(file_path).unwrap();
+    let mut compiler = Compiler::new();
+    match compiler.compile(&byte_stream) {
+        Ok(function) => std::fs::write(output_path, function.to_bytes()).unwrap(),
+        Err(errors) => report(InterpretResult::CompileError(errors)),
+    }
+}
+
+fn run_bytecode(file_path: &str) {
+    let data: Vec<u8> = std::fs::read(file_path).unwrap();
+    let mut vm = VM::new();
+    report(vm.interpret_bytes(&data));
+}
+
 fn main() {
     let argv: Vec<String> = std::env::args().collect();
     if argv.len() == 1 {
         repl();
     } else if argv.len() == 2 {
         run_file(&argv[1]);
+    } else if argv.len() == 4 && argv[1] == "compile" {
+        compile_file(&argv[2], &argv[3]);
+    } else if argv.len() == 3 && argv[1] == "run" {
+        run_bytecode(&argv[2]);
     } else {
-        println!("Usage: clox [path]\n");
+        println!(
+            "Usage: clox [path]\n       clox compile [path] [output]\n       clox run [bytecode]\n"
+        );
     }
 }
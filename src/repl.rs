@@ -0,0 +1,52 @@
+use crate::compiler::*;
+use crate::scanner::*;
+use crate::token::*;
+use crate::vm::*;
+
+// one VM for the whole session, so globals outlive each input
+pub struct Session {
+    vm: VM,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self { vm: VM::new() }
+    }
+
+    pub fn vm(&mut self) -> &mut VM {
+        &mut self.vm
+    }
+
+    pub fn eval(&mut self, source: &str) -> InterpretResult {
+        let mut compiler: Compiler = Compiler::with_interner(self.vm.interner());
+        compiler.set_echo_expressions(true);
+        match compiler.compile(source) {
+            Ok(function) => self.vm.interpret_function(function),
+            Err(errors) => InterpretResult::CompileError(errors),
+        }
+    }
+
+    // unbalanced brackets or an open string wait for more lines
+    pub fn is_incomplete(source: &str) -> bool {
+        let mut scanner: Scanner = Scanner::new();
+        scanner.reset(source);
+        let mut depth: i32 = 0;
+        loop {
+            let token: Token = scanner.scan_token();
+            match token.r#type {
+                TokenType::LeftParen | TokenType::LeftBrace => depth += 1,
+                TokenType::RightParen | TokenType::RightBrace => depth -= 1,
+                TokenType::Error if token.lexeme == "unterminated string" => return true,
+                TokenType::Eof => break,
+                _ => {}
+            }
+        }
+        depth > 0
+    }
+}
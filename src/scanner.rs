@@ -5,6 +5,10 @@ pub struct Scanner {
     start: usize,
     current: usize,
     line: u32,
+    // source index where the current line begins
+    line_start: usize,
+    // line of the previous token when it ends an operand, see skip_white_space
+    operand_line: Option<u32>,
 }
 
 impl Scanner {
@@ -14,14 +18,18 @@ impl Scanner {
             start: 0,
             current: 0,
             line: 1,
+            line_start: 0,
+            operand_line: None,
         }
     }
 
-    pub fn reset(&mut self, source: &String) {
+    pub fn reset(&mut self, source: &str) {
         self.source = source.chars().collect();
         self.start = 0;
         self.current = 0;
         self.line = 1;
+        self.line_start = 0;
+        self.operand_line = None;
     }
 
     pub fn scan_token(&mut self) -> Token {
@@ -42,8 +50,19 @@ impl Scanner {
                     '.' => self.make_token(TokenType::Dot),
                     '+' => self.make_token(TokenType::Plus),
                     '-' => self.make_token(TokenType::Minus),
-                    '*' => self.make_token(TokenType::Star),
-                    '/' => self.make_token(TokenType::Slash),
+                    '*' => match self.r#match('*') {
+                        true => self.make_token(TokenType::StarStar),
+                        false => self.make_token(TokenType::Star),
+                    },
+                    '/' => match self.r#match('/') {
+                        true => self.make_token(TokenType::SlashSlash),
+                        false => self.make_token(TokenType::Slash),
+                    },
+                    '%' => self.make_token(TokenType::Percent),
+                    '~' => self.make_token(TokenType::Tilde),
+                    '&' => self.make_token(TokenType::Ampersand),
+                    '|' => self.make_token(TokenType::Pipe),
+                    '^' => self.make_token(TokenType::Caret),
                     '!' => match self.r#match('=') {
                         true => self.make_token(TokenType::BangEqual),
                         false => self.make_token(TokenType::Bang),
@@ -54,13 +73,23 @@ impl Scanner {
                     },
                     '<' => match self.r#match('=') {
                         true => self.make_token(TokenType::LessEqual),
-                        false => self.make_token(TokenType::Less),
+                        false => match self.r#match('<') {
+                            true => self.make_token(TokenType::LessLess),
+                            false => self.make_token(TokenType::Less),
+                        },
                     },
                     '>' => match self.r#match('=') {
                         true => self.make_token(TokenType::GreaterEqual),
-                        false => self.make_token(TokenType::Greater),
+                        false => match self.r#match('>') {
+                            true => self.make_token(TokenType::GreaterGreater),
+                            false => self.make_token(TokenType::Greater),
+                        },
                     },
                     '"' => self.string_token(),
+                    'r' if self.peek() == Some('"') => {
+                        self.advance();
+                        self.raw_string_token()
+                    }
                     '0'..='9' => self.number_token(),
                     'a'..='z' | 'A'..='Z' | '_' => self.identifier_token(),
                     _ => self.error_token("unexpected character"),
@@ -77,11 +106,14 @@ impl Scanner {
                         self.advance();
                     }
                     '\n' => {
-                        self.line += 1;
+                        self.new_line();
                         self.advance();
                     }
+                    // '//' right after an operand on the same line is floor division,
+                    // anywhere else it starts a comment
                     '/' => match self.peek_next() {
                         Some(pn) => match pn {
+                            '/' if self.operand_line == Some(self.line) => break,
                             '/' => {
                                 while self.peek().is_some() && self.peek().unwrap() != '\n' {
                                     self.advance();
@@ -98,6 +130,15 @@ impl Scanner {
         }
     }
 
+    fn new_line(&mut self) {
+        self.line += 1;
+        self.line_start = self.current + 1;
+    }
+
+    fn column(&self) -> u32 {
+        (self.start.saturating_sub(self.line_start) + 1) as u32
+    }
+
     fn advance(&mut self) {
         self.current += 1;
     }
@@ -131,19 +172,33 @@ impl Scanner {
     }
 
     fn make_token(&mut self, token_type: TokenType) -> Token {
+        self.operand_line = match token_type {
+            TokenType::Identifier
+            | TokenType::String
+            | TokenType::Number
+            | TokenType::RightParen
+            | TokenType::True
+            | TokenType::False
+            | TokenType::Nil
+            | TokenType::This => Some(self.line),
+            _ => None,
+        };
         let lexeme: String = self.source[self.start..self.current].iter().collect();
         Token {
             r#type: token_type,
             lexeme: lexeme,
             line: self.line,
+            column: self.column(),
         }
     }
 
     fn error_token(&mut self, error_info: &str) -> Token {
+        self.operand_line = None;
         Token {
             r#type: TokenType::Error,
             lexeme: error_info.to_string(),
             line: self.line,
+            column: self.column(),
         }
     }
 
@@ -154,14 +209,52 @@ impl Scanner {
                 Some(c) => match c {
                     '"' => break,
                     '\n' => {
-                        self.line += 1;
+                        self.new_line();
                         self.advance();
                     }
+                    // skip the escaped char so '\"' does not end the string
+                    '\\' => {
+                        self.advance();
+                        if self.peek() == Some('\n') {
+                            self.new_line();
+                        }
+                        if self.peek().is_some() {
+                            self.advance();
+                        }
+                    }
                     _ => self.advance(),
                 },
                 None => break,
             }
         }
+        match self.peek().is_none() {
+            true => self.error_token("unterminated string"),
+            false => {
+                self.advance();
+                let body: String = self.source[self.start + 1..self.current - 1]
+                    .iter()
+                    .collect();
+                match unescape(&body) {
+                    Ok(_) => self.make_token(TokenType::String),
+                    Err(e) => self.error_token(&e),
+                }
+            }
+        }
+    }
+
+    // r"..." keeps every char as written, backslashes included
+    fn raw_string_token(&mut self) -> Token {
+        loop {
+            match self.peek() {
+                Some('"') => break,
+                Some('\n') => {
+                    self.new_line();
+                    self.advance();
+                }
+                Some(_) => self.advance(),
+                None => break,
+            }
+        }
         match self.peek().is_none() {
             true => self.error_token("unterminated string"),
             false => {
@@ -221,7 +314,58 @@ impl Scanner {
             "class" => TokenType::Class,
             "this" => TokenType::This,
             "super" => TokenType::Super,
+            "break" => TokenType::Break,
+            "continue" => TokenType::Continue,
             _ => TokenType::Identifier,
         })
     }
 }
+
+// resolve the escapes in a string literal body:
+// \n \t \r \0 \" \\ and \u{XXXX} with one to six hex digits
+pub fn unescape(body: &str) -> Result<String, String> {
+    let mut string: String = String::with_capacity(body.len());
+    let mut chars = body.chars();
+    loop {
+        match chars.next() {
+            Some('\\') => match chars.next() {
+                Some('n') => string.push('\n'),
+                Some('t') => string.push('\t'),
+                Some('r') => string.push('\r'),
+                Some('0') => string.push('\0'),
+                Some('"') => string.push('"'),
+                Some('\\') => string.push('\\'),
+                Some('u') => {
+                    let mut digits: String = String::new();
+                    let mut closed: bool = false;
+                    if chars.next() == Some('{') {
+                        for c in chars.by_ref() {
+                            match c {
+                                '}' => {
+                                    closed = true;
+                                    break;
+                                }
+                                _ => digits.push(c),
+                            }
+                        }
+                    }
+                    let code_point: Option<char> = match closed && (1..=6).contains(&digits.len()) {
+                        true => u32::from_str_radix(&digits, 16)
+                            .ok()
+                            .and_then(char::from_u32),
+                        false => None,
+                    };
+                    match code_point {
+                        Some(c) => string.push(c),
+                        None => return Err(format!("invalid unicode escape '\\u{{{}}}'", digits)),
+                    }
+                }
+                Some(c) => return Err(format!("invalid escape sequence '\\{}'", c)),
+                None => return Err("unterminated escape sequence".to_string()),
+            },
+            Some(c) => string.push(c),
+            None => break,
+        }
+    }
+    Ok(string)
+}
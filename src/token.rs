@@ -12,6 +12,11 @@ pub enum TokenType {
     Semicolon,
     Slash,
     Star,
+    Percent,
+    Ampersand,
+    Pipe,
+    Caret,
+    Tilde,
     // 一或两字符词法
     Bang,
     BangEqual,
@@ -21,6 +26,10 @@ pub enum TokenType {
     GreaterEqual,
     Less,
     LessEqual,
+    StarStar,
+    SlashSlash,
+    LessLess,
+    GreaterGreater,
     // 字面量
     Identifier,
     String,
@@ -42,6 +51,8 @@ pub enum TokenType {
     True,
     Var,
     While,
+    Break,
+    Continue,
     // 特殊词
     Eof,
     Error,
@@ -73,6 +84,11 @@ impl ToString for TokenType {
             TokenType::Semicolon => "Semicolon".to_string(),
             TokenType::Slash => "Slash".to_string(),
             TokenType::Star => "Star".to_string(),
+            TokenType::Percent => "Percent".to_string(),
+            TokenType::Ampersand => "Ampersand".to_string(),
+            TokenType::Pipe => "Pipe".to_string(),
+            TokenType::Caret => "Caret".to_string(),
+            TokenType::Tilde => "Tilde".to_string(),
             TokenType::Bang => "Bang".to_string(),
             TokenType::BangEqual => "BangEqual".to_string(),
             TokenType::Equal => "Equal".to_string(),
@@ -81,6 +97,10 @@ impl ToString for TokenType {
             TokenType::GreaterEqual => "GreaterEqual".to_string(),
             TokenType::Less => "Less".to_string(),
             TokenType::LessEqual => "LessEqual".to_string(),
+            TokenType::StarStar => "StarStar".to_string(),
+            TokenType::SlashSlash => "SlashSlash".to_string(),
+            TokenType::LessLess => "LessLess".to_string(),
+            TokenType::GreaterGreater => "GreaterGreater".to_string(),
             TokenType::Identifier => "Identifier".to_string(),
             TokenType::String => "String".to_string(),
             TokenType::Number => "Number".to_string(),
@@ -100,6 +120,8 @@ impl ToString for TokenType {
             TokenType::True => "True".to_string(),
             TokenType::Var => "Var".to_string(),
             TokenType::While => "While".to_string(),
+            TokenType::Break => "Break".to_string(),
+            TokenType::Continue => "Continue".to_string(),
             TokenType::Eof => "Eof".to_string(),
             TokenType::Error => "Error".to_string(),
         }
@@ -111,4 +133,5 @@ pub struct Token {
     pub r#type: TokenType,
     pub lexeme: String,
     pub line: u32,
+    pub column: u32,
 }
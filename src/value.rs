@@ -1,13 +1,18 @@
-use crate::function::Function;
+use crate::class::{BoundMethod, Class, Instance};
+use crate::function::{Closure, Native};
 use std::rc::Rc;
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub enum Value {
     Bool(bool),
     Nil,
     Number(f64),
-    String(String),
-    Function(Rc<Function>),
+    String(Rc<str>),
+    Closure(Rc<Closure>),
+    Class(Rc<Class>),
+    Instance(Rc<Instance>),
+    BoundMethod(Rc<BoundMethod>),
+    Native(Rc<Native>),
 }
 
 impl ToString for Value {
@@ -17,7 +22,11 @@ impl ToString for Value {
             Value::Nil => "nil".to_string(),
             Value::Number(n) => n.to_string(),
             Value::String(s) => s.to_string(),
-            Value::Function(s) => s.to_string(),
+            Value::Closure(s) => s.to_string(),
+            Value::Class(c) => c.to_string(),
+            Value::Instance(i) => i.to_string(),
+            Value::BoundMethod(b) => b.to_string(),
+            Value::Native(n) => n.to_string(),
         }
     }
 }
@@ -39,7 +48,9 @@ impl std::ops::Add for Value {
     fn add(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
             (Value::Number(x), Value::Number(y)) => Ok(Self::Number(x + y)),
-            (Value::String(x), Value::String(y)) => Ok(Self::String(x + &y)),
+            (Value::String(x), Value::String(y)) => {
+                Ok(Self::String(Rc::from(format!("{}{}", x, y))))
+            }
             _ => Err("Add operation error"),
         }
     }
@@ -78,6 +89,74 @@ impl std::ops::Div for Value {
     }
 }
 
+// floored, so the result takes the sign of the divisor like '//' rounds down
+impl std::ops::Rem for Value {
+    type Output = Result<Self, &'static str>;
+
+    fn rem(self, rhs: Self) -> Self::Output {
+        match (self, rhs) {
+            (Value::Number(x), Value::Number(y)) => Ok(Self::Number(x - y * (x / y).floor())),
+            _ => Err("Mod operation error"),
+        }
+    }
+}
+
+// bitwise operators work on numbers with an exact i64 value
+impl std::ops::BitAnd for Value {
+    type Output = Result<Self, &'static str>;
+
+    fn bitand(self, rhs: Self) -> Self::Output {
+        match (self.integral(), rhs.integral()) {
+            (Some(x), Some(y)) => Ok(Self::Number((x & y) as f64)),
+            _ => Err("BitAnd operation error"),
+        }
+    }
+}
+
+impl std::ops::BitOr for Value {
+    type Output = Result<Self, &'static str>;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        match (self.integral(), rhs.integral()) {
+            (Some(x), Some(y)) => Ok(Self::Number((x | y) as f64)),
+            _ => Err("BitOr operation error"),
+        }
+    }
+}
+
+impl std::ops::BitXor for Value {
+    type Output = Result<Self, &'static str>;
+
+    fn bitxor(self, rhs: Self) -> Self::Output {
+        match (self.integral(), rhs.integral()) {
+            (Some(x), Some(y)) => Ok(Self::Number((x ^ y) as f64)),
+            _ => Err("BitXor operation error"),
+        }
+    }
+}
+
+impl std::ops::Shl for Value {
+    type Output = Result<Self, &'static str>;
+
+    fn shl(self, rhs: Self) -> Self::Output {
+        match (self.integral(), rhs.integral()) {
+            (Some(x), Some(y @ 0..=63)) => Ok(Self::Number((x << y) as f64)),
+            _ => Err("Shift Left operation error"),
+        }
+    }
+}
+
+impl std::ops::Shr for Value {
+    type Output = Result<Self, &'static str>;
+
+    fn shr(self, rhs: Self) -> Self::Output {
+        match (self.integral(), rhs.integral()) {
+            (Some(x), Some(y @ 0..=63)) => Ok(Self::Number((x >> y) as f64)),
+            _ => Err("Shift Right operation error"),
+        }
+    }
+}
+
 impl std::ops::Not for Value {
     type Output = Result<Self, &'static str>;
 
@@ -87,7 +166,11 @@ impl std::ops::Not for Value {
             Value::Nil => Ok(Self::Bool(true)),
             Value::Number(n) => Ok(Self::Bool(n == 0.0)),
             Value::String(s) => Ok(Self::Bool(s.len() == 0)),
-            Value::Function(_) => Ok(Self::Bool(false)),
+            Value::Closure(_) => Ok(Self::Bool(false)),
+            Value::Class(_) => Ok(Self::Bool(false)),
+            Value::Instance(_) => Ok(Self::Bool(false)),
+            Value::BoundMethod(_) => Ok(Self::Bool(false)),
+            Value::Native(_) => Ok(Self::Bool(false)),
         }
     }
 }
@@ -109,8 +192,16 @@ impl Value {
         matches!(self, Self::String(_))
     }
 
-    pub fn is_function(&self) -> bool {
-        matches!(self, Self::Function(_))
+    pub fn is_closure(&self) -> bool {
+        matches!(self, Self::Closure(_))
+    }
+
+    pub fn is_class(&self) -> bool {
+        matches!(self, Self::Class(_))
+    }
+
+    pub fn is_instance(&self) -> bool {
+        matches!(self, Self::Instance(_))
     }
 
     pub fn bool_value(&self) -> bool {
@@ -119,7 +210,39 @@ impl Value {
             Value::Nil => false,
             Value::Number(n) => *n != 0.0,
             Value::String(s) => s.len() != 0,
-            Value::Function(_) => true,
+            Value::Closure(_) => true,
+            Value::Class(_) => true,
+            Value::Instance(_) => true,
+            Value::BoundMethod(_) => true,
+            Value::Native(_) => true,
+        }
+    }
+
+    fn integral(&self) -> Option<i64> {
+        match self {
+            Value::Number(n) if n.fract() == 0.0 && n.abs() < i64::MAX as f64 => Some(*n as i64),
+            _ => None,
+        }
+    }
+
+    pub fn bit_not(&self) -> Result<Self, &'static str> {
+        match self.integral() {
+            Some(x) => Ok(Self::Number(!x as f64)),
+            None => Err("BitNot operation error"),
+        }
+    }
+
+    pub fn power(&self, rhs: &Self) -> Result<Self, &'static str> {
+        match (self, rhs) {
+            (Value::Number(x), Value::Number(y)) => Ok(Self::Number(x.powf(*y))),
+            _ => Err("Pow operation error"),
+        }
+    }
+
+    pub fn floor_divide(&self, rhs: &Self) -> Result<Self, &'static str> {
+        match (self, rhs) {
+            (Value::Number(x), Value::Number(y)) => Ok(Self::Number((x / y).floor())),
+            _ => Err("Floor Div operation error"),
         }
     }
 
@@ -128,11 +251,21 @@ impl Value {
             (Value::Number(x), Value::Number(y)) => Ok(Self::Bool(x == y)),
             (Value::Bool(x), Value::Bool(y)) => Ok(Self::Bool(x == y)),
             (Value::Nil, Value::Nil) => Ok(Self::Bool(0 == 0)),
-            (Value::String(x), Value::String(y)) => Ok(Self::Bool(x == y)),
-            (Value::Function(x), Value::Function(y)) => {
+            // strings are interned, so equal contents share one pointer
+            (Value::String(x), Value::String(y)) => Ok(Self::Bool(Rc::ptr_eq(x, y))),
+            (Value::Closure(x), Value::Closure(y)) => {
+                Ok(Self::Bool(Rc::as_ptr(x) == Rc::as_ptr(y)))
+            }
+            (Value::Class(x), Value::Class(y)) => Ok(Self::Bool(Rc::as_ptr(x) == Rc::as_ptr(y))),
+            (Value::Instance(x), Value::Instance(y)) => {
                 Ok(Self::Bool(Rc::as_ptr(x) == Rc::as_ptr(y)))
             }
-            _ => Err("Equal operation error"),
+            (Value::BoundMethod(x), Value::BoundMethod(y)) => {
+                Ok(Self::Bool(Rc::as_ptr(x) == Rc::as_ptr(y)))
+            }
+            (Value::Native(x), Value::Native(y)) => Ok(Self::Bool(Rc::as_ptr(x) == Rc::as_ptr(y))),
+            // values of different types are never equal
+            _ => Ok(Self::Bool(false)),
         }
     }
 
@@ -141,11 +274,19 @@ impl Value {
             (Value::Number(x), Value::Number(y)) => Ok(Self::Bool(x != y)),
             (Value::Bool(x), Value::Bool(y)) => Ok(Self::Bool(x != y)),
             (Value::Nil, Value::Nil) => Ok(Self::Bool(0 != 0)),
-            (Value::String(x), Value::String(y)) => Ok(Self::Bool(x != y)),
-            (Value::Function(x), Value::Function(y)) => {
+            (Value::String(x), Value::String(y)) => Ok(Self::Bool(!Rc::ptr_eq(x, y))),
+            (Value::Closure(x), Value::Closure(y)) => {
+                Ok(Self::Bool(Rc::as_ptr(x) != Rc::as_ptr(y)))
+            }
+            (Value::Class(x), Value::Class(y)) => Ok(Self::Bool(Rc::as_ptr(x) != Rc::as_ptr(y))),
+            (Value::Instance(x), Value::Instance(y)) => {
                 Ok(Self::Bool(Rc::as_ptr(x) != Rc::as_ptr(y)))
             }
-            _ => Err("Not Equal operation error"),
+            (Value::BoundMethod(x), Value::BoundMethod(y)) => {
+                Ok(Self::Bool(Rc::as_ptr(x) != Rc::as_ptr(y)))
+            }
+            (Value::Native(x), Value::Native(y)) => Ok(Self::Bool(Rc::as_ptr(x) != Rc::as_ptr(y))),
+            _ => Ok(Self::Bool(true)),
         }
     }
 
@@ -155,9 +296,7 @@ impl Value {
             (Value::Bool(x), Value::Bool(y)) => Ok(Self::Bool(x < y)),
             (Value::Nil, Value::Nil) => Ok(Self::Bool(0 < 0)),
             (Value::String(x), Value::String(y)) => Ok(Self::Bool(x < y)),
-            (Value::Function(x), Value::Function(y)) => {
-                Ok(Self::Bool(Rc::as_ptr(x) < Rc::as_ptr(y)))
-            }
+            (Value::Closure(x), Value::Closure(y)) => Ok(Self::Bool(Rc::as_ptr(x) < Rc::as_ptr(y))),
             _ => Err("Less operation error"),
         }
     }
@@ -168,7 +307,7 @@ impl Value {
             (Value::Bool(x), Value::Bool(y)) => Ok(Self::Bool(x <= y)),
             (Value::Nil, Value::Nil) => Ok(Self::Bool(0 <= 0)),
             (Value::String(x), Value::String(y)) => Ok(Self::Bool(x <= y)),
-            (Value::Function(x), Value::Function(y)) => {
+            (Value::Closure(x), Value::Closure(y)) => {
                 Ok(Self::Bool(Rc::as_ptr(x) <= Rc::as_ptr(y)))
             }
             _ => Err("Less Equal operation error"),
@@ -181,9 +320,7 @@ impl Value {
             (Value::Bool(x), Value::Bool(y)) => Ok(Self::Bool(x > y)),
             (Value::Nil, Value::Nil) => Ok(Self::Bool(0 > 0)),
             (Value::String(x), Value::String(y)) => Ok(Self::Bool(x > y)),
-            (Value::Function(x), Value::Function(y)) => {
-                Ok(Self::Bool(Rc::as_ptr(x) > Rc::as_ptr(y)))
-            }
+            (Value::Closure(x), Value::Closure(y)) => Ok(Self::Bool(Rc::as_ptr(x) > Rc::as_ptr(y))),
             _ => Err("Greater operation error"),
         }
     }
@@ -194,7 +331,7 @@ impl Value {
             (Value::Bool(x), Value::Bool(y)) => Ok(Self::Bool(x >= y)),
             (Value::Nil, Value::Nil) => Ok(Self::Bool(0 >= 0)),
             (Value::String(x), Value::String(y)) => Ok(Self::Bool(x >= y)),
-            (Value::Function(x), Value::Function(y)) => {
+            (Value::Closure(x), Value::Closure(y)) => {
                 Ok(Self::Bool(Rc::as_ptr(x) >= Rc::as_ptr(y)))
             }
             _ => Err("Greater Equal operation error"),
@@ -0,0 +1,382 @@
+use crate::bytecode::NESTING_MAX;
+use crate::chunk::{Chunk, OpCode};
+use crate::function::Function;
+use std::rc::Rc;
+
+// static checks for bytecode that did not come straight from the compiler:
+// every opcode is known, every operand stays inside its pool, every jump lands
+// on an instruction, and every path through the code agrees on the stack depth
+impl Function {
+    // the vm runs a script with no arguments and no enclosing upvalues
+    pub fn verify(&self) -> Result<(), String> {
+        if self.params_num != 0 || self.upvalue_count != 0 {
+            return Err(format!(
+                "Invalid bytecode in {}: a script takes no parameters or upvalues, found {} and {}",
+                self.to_string(),
+                self.params_num,
+                self.upvalue_count
+            ));
+        }
+        self.verify_function(1)
+    }
+
+    fn verify_function(&self, depth: usize) -> Result<(), String> {
+        if depth > NESTING_MAX {
+            return Err(format!(
+                "Invalid bytecode in {}: functions nested too deeply, the limit is {}",
+                self.to_string(),
+                NESTING_MAX
+            ));
+        }
+        let error = |offset: usize, message: String| -> String {
+            format!(
+                "Invalid bytecode in {} at offset {}: {}",
+                self.to_string(),
+                offset,
+                message
+            )
+        };
+        let chunk: &Chunk = &self.chunk;
+        if chunk.lines().len() != chunk.code_size() {
+            return Err(format!(
+                "Invalid bytecode in {}: {} code bytes but {} lines",
+                self.to_string(),
+                chunk.code_size(),
+                chunk.lines().len()
+            ));
+        }
+
+        let mut starts: Vec<bool> = vec![false; chunk.code_size()];
+        let mut offset: usize = 0;
+        while offset < chunk.code_size() {
+            starts[offset] = true;
+            offset += self
+                .check_instruction(offset)
+                .map_err(|message| error(offset, message))?;
+        }
+        for (offset, _) in starts.iter().enumerate().filter(|(_, start)| **start) {
+            if let Some(target) = jump_target(chunk, offset).map_err(|m| error(offset, m))? {
+                if target >= chunk.code_size() || !starts[target] {
+                    return Err(error(
+                        offset,
+                        format!("Jump target {} is not the start of an instruction", target),
+                    ));
+                }
+            }
+        }
+
+        self.check_stack().map_err(|(offset, m)| error(offset, m))?;
+        for function in chunk.functions() {
+            function.verify_function(depth + 1)?;
+        }
+        Ok(())
+    }
+
+    // decode one instruction and check its operands, returns its size
+    fn check_instruction(&self, offset: usize) -> Result<usize, String> {
+        let chunk: &Chunk = &self.chunk;
+        let instruction: OpCode = chunk.read_code(offset).try_into()?;
+        let operand_size: usize = match instruction.is_long() {
+            true => 3,
+            false => 1,
+        };
+        let truncated = |size: usize| -> Result<usize, String> {
+            match offset + size <= chunk.code_size() {
+                true => Ok(size),
+                false => Err(format!("{} is cut off", instruction.to_string())),
+            }
+        };
+        let in_pool = |pool: &str, len: usize| -> Result<usize, String> {
+            let size: usize = truncated(1 + operand_size)?;
+            let index: usize = chunk.read_index(&instruction, offset + 1);
+            match index < len {
+                true => Ok(size),
+                false => Err(format!(
+                    "{} index {} is outside the {} {} constants",
+                    instruction.to_string(),
+                    index,
+                    len,
+                    pool
+                )),
+            }
+        };
+
+        match instruction {
+            OpCode::Number | OpCode::NumberLong => in_pool("number", chunk.numbers().len()),
+            OpCode::String
+            | OpCode::StringLong
+            | OpCode::DefineGlobal
+            | OpCode::DefineGlobalLong
+            | OpCode::GetGlobal
+            | OpCode::GetGlobalLong
+            | OpCode::SetGlobal
+            | OpCode::SetGlobalLong
+            | OpCode::Class
+            | OpCode::ClassLong
+            | OpCode::GetProperty
+            | OpCode::GetPropertyLong
+            | OpCode::SetProperty
+            | OpCode::SetPropertyLong
+            | OpCode::Method
+            | OpCode::MethodLong
+            | OpCode::GetSuper
+            | OpCode::GetSuperLong => in_pool("string", chunk.strings().len()),
+            OpCode::GetLocal | OpCode::GetLocalLong | OpCode::SetLocal | OpCode::SetLocalLong => {
+                in_pool("variable", chunk.variables().len())
+            }
+            OpCode::Closure | OpCode::ClosureLong => {
+                in_pool("function", chunk.functions().len())?;
+                let index: usize = chunk.read_index(&instruction, offset + 1);
+                let function: &Rc<Function> = &chunk.functions()[index];
+                let size: usize = truncated(1 + operand_size + function.upvalue_count * 2)?;
+                for i in 0..function.upvalue_count {
+                    let capture: usize = offset + 1 + operand_size + i * 2;
+                    let index: usize = chunk.read_code(capture + 1) as usize;
+                    match chunk.read_code(capture) {
+                        0 if index >= self.upvalue_count => {
+                            return Err(format!(
+                                "Captured upvalue {} is outside the {} upvalues of {}",
+                                index,
+                                self.upvalue_count,
+                                self.to_string()
+                            ))
+                        }
+                        0 | 1 => {}
+                        flag => return Err(format!("Invalid capture flag {}", flag)),
+                    }
+                }
+                Ok(size)
+            }
+            OpCode::GetUpvalue | OpCode::SetUpvalue => {
+                let size: usize = truncated(2)?;
+                let index: usize = chunk.read_code(offset + 1) as usize;
+                match index < self.upvalue_count {
+                    true => Ok(size),
+                    false => Err(format!(
+                        "{} slot {} is outside the {} upvalues",
+                        instruction.to_string(),
+                        index,
+                        self.upvalue_count
+                    )),
+                }
+            }
+            OpCode::Call => truncated(2),
+            OpCode::JumpFalse | OpCode::Jump | OpCode::JumpBack => truncated(3),
+            OpCode::JumpFalseWide | OpCode::JumpWide | OpCode::JumpBackWide => truncated(5),
+            _ => truncated(1),
+        }
+    }
+
+    // walk every reachable path from the entry, the callee and its arguments
+    // are already on the stack when a frame starts. Along the way track which
+    // slots closures captured, those must be closed before they leave the stack
+    fn check_stack(&self) -> Result<(), (usize, String)> {
+        let chunk: &Chunk = &self.chunk;
+        let mut seen: Vec<Option<(usize, Vec<usize>)>> = vec![None; chunk.code_size()];
+        let mut pending: Vec<(usize, usize, usize, Vec<usize>)> =
+            vec![(0, 0, 1 + self.params_num, Vec::new())];
+        while let Some((from, offset, depth, mut captured)) = pending.pop() {
+            if offset >= chunk.code_size() {
+                return Err((from, "Execution runs past the end of the chunk".to_string()));
+            }
+            match &seen[offset] {
+                Some((seen_depth, _)) if *seen_depth != depth => {
+                    return Err((
+                        offset,
+                        format!(
+                            "Stack depth {} here does not match depth {} on another path",
+                            depth, seen_depth
+                        ),
+                    ));
+                }
+                Some((_, seen_captured)) if *seen_captured != captured => {
+                    return Err((
+                        offset,
+                        format!(
+                            "Captured slots {:?} here do not match {:?} on another path",
+                            captured, seen_captured
+                        ),
+                    ));
+                }
+                Some(_) => continue,
+                None => seen[offset] = Some((depth, captured.clone())),
+            }
+
+            let instruction: OpCode = chunk.read_op_code(offset);
+            let (pops, pushes): (usize, usize) = stack_effect(chunk, &instruction, offset);
+            if depth < pops {
+                return Err((
+                    offset,
+                    format!(
+                        "{} needs {} stack values but only {} are there",
+                        instruction.to_string(),
+                        pops,
+                        depth
+                    ),
+                ));
+            }
+            match instruction {
+                OpCode::GetLocal
+                | OpCode::GetLocalLong
+                | OpCode::SetLocal
+                | OpCode::SetLocalLong => {
+                    let index: usize = chunk.read_index(&instruction, offset + 1);
+                    let slot: usize = *chunk.read_variable(index);
+                    if slot >= depth {
+                        return Err((
+                            offset,
+                            format!("Local slot {} is outside the stack depth {}", slot, depth),
+                        ));
+                    }
+                }
+                OpCode::Closure | OpCode::ClosureLong => {
+                    let index: usize = chunk.read_index(&instruction, offset + 1);
+                    let function: &Rc<Function> = chunk.read_function(index);
+                    let captures: usize =
+                        offset + chunk.instruction_size(offset) - function.upvalue_count * 2;
+                    // a local function captures itself from the slot the closure is about to fill
+                    for i in 0..function.upvalue_count {
+                        let slot: usize = chunk.read_code(captures + i * 2 + 1) as usize;
+                        if chunk.read_code(captures + i * 2) != 1 {
+                            continue;
+                        }
+                        if slot > depth {
+                            return Err((
+                                offset,
+                                format!(
+                                    "Captured local slot {} is outside the stack depth {}",
+                                    slot, depth
+                                ),
+                            ));
+                        }
+                        if !captured.contains(&slot) {
+                            captured.push(slot);
+                        }
+                    }
+                    captured.sort_unstable();
+                }
+                OpCode::CloseUpvalue => captured.retain(|slot| *slot != depth - 1),
+                _ => {}
+            }
+
+            let depth: usize = depth - pops + pushes;
+            // a return closes every upvalue of the frame, anything else has to
+            // leave captured slots on the stack or the vm reads past its end
+            if !matches!(instruction, OpCode::Return) {
+                if let Some(slot) = captured.iter().find(|slot| **slot >= depth) {
+                    return Err((
+                        offset,
+                        format!(
+                            "{} drops captured local slot {} without closing it",
+                            instruction.to_string(),
+                            slot
+                        ),
+                    ));
+                }
+            }
+            let next: usize = offset + chunk.instruction_size(offset);
+            match instruction {
+                OpCode::Return => {}
+                OpCode::Jump | OpCode::JumpWide | OpCode::JumpBack | OpCode::JumpBackWide => {
+                    let target: usize = jump_target(chunk, offset).unwrap().unwrap();
+                    pending.push((offset, target, depth, captured));
+                }
+                OpCode::JumpFalse | OpCode::JumpFalseWide => {
+                    let target: usize = jump_target(chunk, offset).unwrap().unwrap();
+                    pending.push((offset, target, depth, captured.clone()));
+                    pending.push((offset, next, depth, captured));
+                }
+                _ => pending.push((offset, next, depth, captured)),
+            }
+        }
+        Ok(())
+    }
+}
+
+// absolute target of the jump at offset, None for any other instruction
+fn jump_target(chunk: &Chunk, offset: usize) -> Result<Option<usize>, String> {
+    let instruction: OpCode = chunk.read_op_code(offset);
+    let operand_size: usize = match instruction {
+        OpCode::JumpFalse | OpCode::Jump | OpCode::JumpBack => 2,
+        OpCode::JumpFalseWide | OpCode::JumpWide | OpCode::JumpBackWide => 4,
+        _ => return Ok(None),
+    };
+    let distance: usize = (0..operand_size).fold(0, |distance, i| {
+        distance | (chunk.read_code(offset + 1 + i) as usize) << (i * 8)
+    });
+    let next: usize = offset + 1 + operand_size;
+    match instruction {
+        OpCode::JumpBack | OpCode::JumpBackWide => match next.checked_sub(distance) {
+            Some(target) => Ok(Some(target)),
+            None => Err(format!(
+                "{} jumps before the start of the chunk",
+                instruction.to_string()
+            )),
+        },
+        _ => Ok(Some(next + distance)),
+    }
+}
+
+// values an instruction pops and pushes, matching what VM::run does
+fn stack_effect(chunk: &Chunk, instruction: &OpCode, offset: usize) -> (usize, usize) {
+    match instruction {
+        OpCode::Nil
+        | OpCode::True
+        | OpCode::False
+        | OpCode::Number
+        | OpCode::NumberLong
+        | OpCode::String
+        | OpCode::StringLong
+        | OpCode::Closure
+        | OpCode::ClosureLong
+        | OpCode::GetGlobal
+        | OpCode::GetGlobalLong
+        | OpCode::GetLocal
+        | OpCode::GetLocalLong
+        | OpCode::GetUpvalue
+        | OpCode::Class
+        | OpCode::ClassLong => (0, 1),
+        OpCode::Not
+        | OpCode::Negate
+        | OpCode::BitNot
+        | OpCode::SetGlobal
+        | OpCode::SetGlobalLong
+        | OpCode::SetLocal
+        | OpCode::SetLocalLong
+        | OpCode::SetUpvalue
+        | OpCode::GetProperty
+        | OpCode::GetPropertyLong
+        | OpCode::JumpFalse
+        | OpCode::JumpFalseWide => (1, 1),
+        OpCode::Addition
+        | OpCode::Subtract
+        | OpCode::Multiply
+        | OpCode::Divide
+        | OpCode::Modulo
+        | OpCode::Power
+        | OpCode::FloorDivide
+        | OpCode::BitAnd
+        | OpCode::BitOr
+        | OpCode::BitXor
+        | OpCode::ShiftLeft
+        | OpCode::ShiftRight
+        | OpCode::Equal
+        | OpCode::Greater
+        | OpCode::Less
+        | OpCode::SetProperty
+        | OpCode::SetPropertyLong
+        | OpCode::GetSuper
+        | OpCode::GetSuperLong
+        | OpCode::Method
+        | OpCode::MethodLong
+        | OpCode::Inherit => (2, 1),
+        OpCode::Return
+        | OpCode::Print
+        | OpCode::Pop
+        | OpCode::CloseUpvalue
+        | OpCode::DefineGlobal
+        | OpCode::DefineGlobalLong => (1, 0),
+        OpCode::Call => (chunk.read_code(offset + 1) as usize + 1, 1),
+        OpCode::Jump | OpCode::JumpWide | OpCode::JumpBack | OpCode::JumpBackWide => (0, 0),
+    }
+}
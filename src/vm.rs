@@ -1,117 +1,264 @@
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::rc::Rc;
 
 use crate::chunk::*;
+use crate::class::*;
 use crate::compiler::*;
+use crate::error::*;
 use crate::function::*;
+use crate::gc::{Heap, Object};
+use crate::interner::Interner;
 use crate::value::Value;
 
+#[derive(Debug)]
 pub enum InterpretResult {
-    Success = 0,
-    CompileError,
-    RuntimeError,
+    Success,
+    CompileError(Vec<CompileError>),
+    RuntimeError(RuntimeError),
+    LoadError(String),
 }
 
 impl ToString for InterpretResult {
     fn to_string(&self) -> String {
         match self {
             InterpretResult::Success => "Success".to_string(),
-            InterpretResult::CompileError => "CompileError".to_string(),
-            InterpretResult::RuntimeError => "RuntimeError".to_string(),
+            InterpretResult::CompileError(errors) => errors
+                .iter()
+                .map(|error| error.to_string())
+                .collect::<Vec<String>>()
+                .join("\n"),
+            InterpretResult::RuntimeError(error) => error.to_string(),
+            InterpretResult::LoadError(message) => message.clone(),
         }
     }
 }
 
 struct CallFrame {
-    ip: RefCell<usize>,
-    function: Rc<Function>,
+    // only up to date while the frame is suspended, run keeps the live ip local
+    ip: usize,
+    closure: Rc<Closure>,
     slot: usize,
 }
 
+// default limits, adjustable per VM
+pub const FRAMES_MAX: usize = 1024;
+pub const STACK_MAX: usize = FRAMES_MAX * 256;
+
 pub struct VM {
     frames: Vec<CallFrame>,
     stack: Vec<Value>,
-    globals: Vec<Option<Value>>,
+    max_frames: usize,
+    max_stack: usize,
+    globals: HashMap<Rc<str>, Value>,
+    interner: Rc<RefCell<Interner>>,
+    // sorted by stack slot, innermost last
+    open_upvalues: Vec<Rc<RefCell<Upvalue>>>,
+    heap: Heap,
 }
 
 macro_rules! push_constant {
-    ($vm: expr, $value_type: ident, $read_op: ident) => {{
-        let index: usize = $vm.read_byte() as usize;
-        let value: Value = Value::$value_type($vm.curr_chunk().$read_op(index).clone());
+    ($vm: expr, $chunk: expr, $ip: expr, $instruction: expr, $value_type: ident, $read_op: ident) => {{
+        let index: usize = VM::read_index(&$chunk, &mut $ip, &$instruction);
+        let value: Value = Value::$value_type($chunk.$read_op(index).clone());
         $vm.stack.push(value);
     }};
 }
 
 macro_rules! unary_op {
-    ($vm: expr, $op: expr) => {{
+    ($vm: expr, $ip: expr, $op: expr) => {{
         let top = $vm.stack.pop().unwrap();
         match $op(top) {
             Ok(v) => $vm.stack.push(v),
             Err(msg) => {
-                $vm.runtime_error(msg);
-                break InterpretResult::RuntimeError;
+                break InterpretResult::RuntimeError($vm.runtime_error_at($ip, msg));
             }
         }
     }};
 }
 
 macro_rules! binary_op {
-    ($vm: expr, $op: expr) => {{
+    ($vm: expr, $ip: expr, $op: expr) => {{
         let b: Value = $vm.stack.pop().unwrap();
         let a: Value = $vm.stack.pop().unwrap();
         match $op(a, b) {
             Ok(v) => $vm.stack.push(v),
             Err(msg) => {
-                $vm.runtime_error(msg);
-                break InterpretResult::RuntimeError;
+                break InterpretResult::RuntimeError($vm.runtime_error_at($ip, msg));
             }
         }
     }};
 }
 
+impl Default for VM {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl VM {
     pub fn new() -> Self {
-        VM {
+        let mut vm = VM {
             frames: Vec::<CallFrame>::new(),
             stack: Vec::<Value>::new(),
-            globals: Vec::<Option<Value>>::new(),
+            max_frames: FRAMES_MAX,
+            max_stack: STACK_MAX,
+            globals: HashMap::<Rc<str>, Value>::new(),
+            interner: Rc::new(RefCell::new(Interner::new())),
+            open_upvalues: Vec::<Rc<RefCell<Upvalue>>>::new(),
+            heap: Heap::new(),
+        };
+        vm.define_native("clock", 0, clock_native);
+        vm
+    }
+
+    pub fn define_native(&mut self, name: &str, arity: usize, function: NativeFn) {
+        let native = Rc::new(Native {
+            name: name.to_string(),
+            arity,
+            function,
+        });
+        self.globals.insert(Rc::from(name), Value::Native(native));
+    }
+
+    pub fn intern_string(&mut self, string: &str) -> Value {
+        Value::String(self.interner.borrow_mut().intern(string))
+    }
+
+    // collect on every allocation, to shake out missing roots
+    pub fn set_gc_stress(&mut self, stress: bool) {
+        self.heap.set_stress(stress);
+    }
+
+    pub fn set_gc_log(&mut self, log: bool) {
+        self.heap.set_log(log);
+    }
+
+    // live objects the collector is tracking
+    pub fn heap_size(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn collect_garbage(&mut self) -> usize {
+        if self.heap.is_log() {
+            eprintln!("-- gc begin");
+        }
+        let freed: usize = self.heap.collect(
+            self.stack.iter().chain(self.globals.values()),
+            self.frames.iter().map(|frame| &frame.closure),
+            self.open_upvalues.iter(),
+        );
+        let pruned: usize = self.interner.borrow_mut().prune();
+        if self.heap.is_log() {
+            eprintln!(
+                "-- gc end: freed {} objects, {} remain, pruned {} strings",
+                freed,
+                self.heap.len(),
+                pruned
+            );
         }
+        freed
     }
 
-    pub fn interpret_source(&mut self, source: &String) -> InterpretResult {
-        let mut compiler: Compiler = Compiler::new();
+    pub(crate) fn interner(&self) -> Rc<RefCell<Interner>> {
+        self.interner.clone()
+    }
+
+    pub fn set_max_frames(&mut self, max_frames: usize) {
+        self.max_frames = max_frames;
+    }
+
+    pub fn set_max_stack(&mut self, max_stack: usize) {
+        self.max_stack = max_stack;
+    }
+
+    pub fn interpret_source(&mut self, source: &str) -> InterpretResult {
+        let mut compiler: Compiler = Compiler::with_interner(self.interner.clone());
         match compiler.compile(source) {
-            Ok(function) => {
-                self.stack.clear();
-                self.globals.clear();
-                self.globals.resize(256, Option::None);
-
-                let rc_fun = Rc::new(function);
-                let fun_value = Value::Function(rc_fun.clone());
-                self.stack.push(fun_value);
-                self.frames.push(CallFrame {
-                    function: rc_fun,
-                    ip: RefCell::new(0),
-                    slot: self.stack.len(),
-                });
-                self.run()
-            }
-            Err(_) => InterpretResult::CompileError,
+            Ok(function) => self.interpret_function(function),
+            Err(errors) => InterpretResult::CompileError(errors),
+        }
+    }
+
+    // run a script saved with Function::to_bytes, no compiler involved, so
+    // the code is verified before the vm trusts its operands
+    pub fn interpret_bytes(&mut self, bytes: &[u8]) -> InterpretResult {
+        match Function::from_bytes(bytes).and_then(|f| f.verify().map(|_| f)) {
+            Ok(function) => self.interpret_function(function),
+            Err(message) => InterpretResult::LoadError(message),
         }
     }
 
-    // pub fn interpret_chunk(&mut self, chunk: &Chunk) -> InterpretResult {
-    //     self.stack.clear();
-    //     self.globals.clear();
-    //     self.globals.resize(256, Option::None);
-    //     self.run(chunk)
-    // }
+    pub fn interpret_function(&mut self, mut function: Function) -> InterpretResult {
+        self.stack.clear();
+        // functions compiled elsewhere may hold strings from another table
+        Rc::make_mut(&mut function.chunk).intern_strings(&mut self.interner.borrow_mut());
+
+        let rc_closure = Rc::new(Closure {
+            function: Rc::new(function),
+            upvalues: Vec::new(),
+        });
+        self.stack.push(Value::Closure(rc_closure.clone()));
+        self.track(Object::Closure(rc_closure.clone()));
+        self.frames.push(CallFrame {
+            closure: rc_closure,
+            ip: 0,
+            slot: self.stack.len() - 1,
+        });
+        let result: InterpretResult = self.run(0);
+        if let InterpretResult::RuntimeError(_) = result {
+            self.reset_stack();
+        }
+        result
+    }
+
+    // natives may call back in while a script is running, so a failed call
+    // only unwinds what it pushed and leaves the caller's frames alone
+    pub fn call_global(&mut self, name: &str, args: &[Value]) -> Result<Value, RuntimeError> {
+        let base_frame: usize = self.frames.len();
+        let base_stack: usize = self.stack.len();
+        let result: Result<Value, RuntimeError> = self.call_global_value(name, args);
+        if result.is_err() {
+            // the call already failed, a second error here adds nothing
+            let _ = self.close_upvalues(base_stack);
+            self.stack.truncate(base_stack);
+            self.frames.truncate(base_frame);
+        }
+        result
+    }
+
+    fn call_global_value(&mut self, name: &str, args: &[Value]) -> Result<Value, RuntimeError> {
+        let callee: Value = match self.globals.get(name) {
+            Some(v) => v.clone(),
+            None => return Err(self.runtime_error(&format!("Undefined variable '{}'", name))),
+        };
+        let base_frame: usize = self.frames.len();
+        self.stack.push(callee);
+        for arg in args {
+            let arg: Value = self.intern_value(arg.clone());
+            self.stack.push(arg);
+        }
+        self.call_value(args.len())?;
+        // natives and classes without init finish inside call_value
+        if self.frames.len() > base_frame {
+            if let InterpretResult::RuntimeError(error) = self.run(base_frame) {
+                return Err(error);
+            }
+        }
+        Ok(self.stack.pop().unwrap())
+    }
 
     pub fn reset_stack(&mut self) {
         self.stack = Vec::<Value>::new();
+        self.frames.clear();
+        self.open_upvalues.clear();
     }
 
-    fn run(&mut self) -> InterpretResult {
+    // runs until the frame stack shrinks back to base_frame
+    fn run(&mut self, base_frame: usize) -> InterpretResult {
+        // hot dispatch state, written back to the frame only around calls and errors
+        let mut chunk: Rc<Chunk> = self.curr_chunk();
+        let mut ip: usize = self.curr_frame().ip;
         let interpret_result = {
             loop {
                 #[cfg(debug_assertions)]
@@ -124,110 +271,142 @@ impl VM {
                     //         _ => println!("{:^16}", value.to_string()),
                     //     }
                     // }
-                    // self.curr_chunk().disassemble_instruction(self.curr_ip());
+                    // chunk.disassemble_instruction(ip);
                 }
-                let instruction: OpCode = self.read_byte().into();
+                let instruction: OpCode = match Self::read_byte(&chunk, &mut ip).try_into() {
+                    Ok(instruction) => instruction,
+                    Err(message) => {
+                        break InterpretResult::RuntimeError(self.runtime_error_at(ip, &message));
+                    }
+                };
                 match instruction {
                     OpCode::Return => {
+                        if let Err(message) = self.close_upvalues(self.curr_frame().slot) {
+                            break InterpretResult::RuntimeError(
+                                self.runtime_error_at(ip, &message),
+                            );
+                        }
                         let result = self.stack.pop().unwrap();
                         let frame = self.frames.pop().unwrap();
-                        if self.frames.len() == 0 {
+                        self.stack.truncate(frame.slot);
+                        self.stack.push(result);
+                        if self.frames.len() == base_frame {
                             break InterpretResult::Success;
                         }
-                        self.stack.truncate(frame.slot - 1);
-                        self.stack.push(result);
+                        chunk = self.curr_chunk();
+                        ip = self.curr_frame().ip;
                     }
                     OpCode::Nil => self.stack.push(Value::Nil),
                     OpCode::True => self.stack.push(Value::Bool(true)),
                     OpCode::False => self.stack.push(Value::Bool(false)),
-                    OpCode::Number => push_constant!(self, Number, read_number),
-                    OpCode::String => push_constant!(self, String, read_string),
-                    OpCode::Function => push_constant!(self, Function, read_function),
-                    OpCode::Equal => binary_op!(self, |x: Value, y: Value| x.equal(&y)),
-                    OpCode::Greater => binary_op!(self, |x: Value, y: Value| x.greater(&y)),
-                    OpCode::Less => binary_op!(self, |x: Value, y: Value| x.less(&y)),
-                    OpCode::Not => unary_op!(self, |x: Value| !x),
-                    OpCode::Negate => unary_op!(self, |x: Value| -x),
-                    OpCode::Addition => binary_op!(self, |x: Value, y: Value| x + y),
-                    OpCode::Subtract => binary_op!(self, |x: Value, y: Value| x - y),
-                    OpCode::Multiply => binary_op!(self, |x: Value, y: Value| x * y),
-                    OpCode::Divide => binary_op!(self, |x: Value, y: Value| x / y),
+                    OpCode::Number | OpCode::NumberLong => {
+                        push_constant!(self, chunk, ip, instruction, Number, read_number)
+                    }
+                    OpCode::String | OpCode::StringLong => {
+                        push_constant!(self, chunk, ip, instruction, String, read_string)
+                    }
+                    OpCode::Closure | OpCode::ClosureLong => {
+                        let index: usize = Self::read_index(&chunk, &mut ip, &instruction);
+                        let function: Rc<Function> = chunk.read_function(index).clone();
+                        let mut upvalues = Vec::<Rc<RefCell<Upvalue>>>::new();
+                        for _ in 0..function.upvalue_count {
+                            let is_local: bool = Self::read_byte(&chunk, &mut ip) == 1;
+                            let index: usize = Self::read_byte(&chunk, &mut ip) as usize;
+                            upvalues.push(match is_local {
+                                true => self.capture_upvalue(self.curr_frame().slot + index),
+                                false => self.curr_frame().closure.upvalues[index].clone(),
+                            });
+                        }
+                        let closure = Rc::new(Closure { function, upvalues });
+                        self.stack.push(Value::Closure(closure.clone()));
+                        self.track(Object::Closure(closure));
+                    }
+                    OpCode::Equal => binary_op!(self, ip, |x: Value, y: Value| x.equal(&y)),
+                    OpCode::Greater => binary_op!(self, ip, |x: Value, y: Value| x.greater(&y)),
+                    OpCode::Less => binary_op!(self, ip, |x: Value, y: Value| x.less(&y)),
+                    OpCode::Not => unary_op!(self, ip, |x: Value| !x),
+                    OpCode::Negate => unary_op!(self, ip, |x: Value| -x),
+                    OpCode::Addition => {
+                        binary_op!(self, ip, |x: Value, y: Value| x + y);
+                        // concatenation results join the intern table
+                        if let Some(Value::String(string)) = self.stack.last_mut() {
+                            *string = self.interner.borrow_mut().intern_rc(string.clone());
+                        }
+                    }
+                    OpCode::Subtract => binary_op!(self, ip, |x: Value, y: Value| x - y),
+                    OpCode::Multiply => binary_op!(self, ip, |x: Value, y: Value| x * y),
+                    OpCode::Divide => binary_op!(self, ip, |x: Value, y: Value| x / y),
+                    OpCode::Modulo => binary_op!(self, ip, |x: Value, y: Value| x % y),
+                    OpCode::Power => binary_op!(self, ip, |x: Value, y: Value| x.power(&y)),
+                    OpCode::FloorDivide => {
+                        binary_op!(self, ip, |x: Value, y: Value| x.floor_divide(&y))
+                    }
+                    OpCode::BitAnd => binary_op!(self, ip, |x: Value, y: Value| x & y),
+                    OpCode::BitOr => binary_op!(self, ip, |x: Value, y: Value| x | y),
+                    OpCode::BitXor => binary_op!(self, ip, |x: Value, y: Value| x ^ y),
+                    OpCode::BitNot => unary_op!(self, ip, |x: Value| x.bit_not()),
+                    OpCode::ShiftLeft => binary_op!(self, ip, |x: Value, y: Value| x << y),
+                    OpCode::ShiftRight => binary_op!(self, ip, |x: Value, y: Value| x >> y),
                     OpCode::Print => println!("{}", self.stack.pop().unwrap().to_string()),
                     OpCode::Pop => {
                         let _ = self.stack.pop().unwrap();
                     }
-                    OpCode::DefineGlobal => {
-                        let index: usize = self.read_byte() as usize;
-                        let slot: usize = self.curr_chunk().read_variable(index).clone();
+                    OpCode::DefineGlobal | OpCode::DefineGlobalLong => {
+                        let index: usize = Self::read_index(&chunk, &mut ip, &instruction);
+                        let name: Rc<str> = chunk.read_string(index).clone();
                         let value: Value = self.stack.pop().unwrap();
-                        if slot >= self.globals.len() {
-                            self.runtime_error(&format!(
-                                "Global variable slot only in 0 ~ {}",
-                                self.globals.len() - 1
-                            ));
-                            break InterpretResult::RuntimeError;
-                        }
-                        match &self.globals[slot] {
-                            Some(_) => {
-                                self.runtime_error("Redefine global variable");
-                                break InterpretResult::RuntimeError;
-                            }
-                            None => self.globals[slot] = Some(value),
-                        }
+                        // redefinition replaces the old value, as in the repl or over a native
+                        self.globals.insert(name, value);
                     }
-                    OpCode::GetGlobal => {
-                        let index: usize = self.read_byte() as usize;
-                        let global_slot: usize = self.curr_chunk().read_variable(index).clone();
-                        match &self.globals[global_slot] {
+                    OpCode::GetGlobal | OpCode::GetGlobalLong => {
+                        let index: usize = Self::read_index(&chunk, &mut ip, &instruction);
+                        let name: Rc<str> = chunk.read_string(index).clone();
+                        match self.globals.get(&name) {
                             Some(v) => {
                                 self.stack.push(v.clone());
                             }
                             None => {
-                                self.runtime_error(&format!(
-                                    "Undefined variable in global slot[{}]",
-                                    global_slot
+                                break InterpretResult::RuntimeError(self.runtime_error_at(
+                                    ip,
+                                    &format!("Undefined variable '{}'", name),
                                 ));
-                                break InterpretResult::RuntimeError;
                             }
                         }
                     }
-                    OpCode::SetGlobal => {
-                        let index: usize = self.read_byte() as usize;
-                        let global_slot: usize = self.curr_chunk().read_variable(index).clone();
-                        match &self.globals[global_slot] {
-                            Some(_) => {
-                                let value: &Value = self.stack.last().unwrap();
-                                self.globals[global_slot] = Some(value.clone());
+                    OpCode::SetGlobal | OpCode::SetGlobalLong => {
+                        let index: usize = Self::read_index(&chunk, &mut ip, &instruction);
+                        let name: Rc<str> = chunk.read_string(index).clone();
+                        match self.globals.get_mut(&name) {
+                            Some(global) => {
+                                *global = self.stack.last().unwrap().clone();
                             }
                             None => {
-                                self.runtime_error(&format!(
-                                    "Undefined variable in global slot[{}]",
-                                    global_slot
+                                break InterpretResult::RuntimeError(self.runtime_error_at(
+                                    ip,
+                                    &format!("Undefined variable '{}'", name),
                                 ));
-                                break InterpretResult::RuntimeError;
                             }
                         }
                     }
-                    OpCode::GetLocal => {
-                        let index: usize = self.read_byte() as usize;
-                        let local_slot: usize = self.curr_chunk().read_variable(index).clone();
+                    OpCode::GetLocal | OpCode::GetLocalLong => {
+                        let index: usize = Self::read_index(&chunk, &mut ip, &instruction);
+                        let local_slot: usize = chunk.read_variable(index).clone();
                         let stack_slot = local_slot + self.curr_frame().slot;
                         match self.stack.get(stack_slot) {
                             Some(v) => {
                                 self.stack.push(v.clone());
                             }
                             None => {
-                                self.runtime_error(&format!(
-                                    "Undefined variable in stack slot[{}]",
-                                    stack_slot
+                                break InterpretResult::RuntimeError(self.runtime_error_at(
+                                    ip,
+                                    &format!("Undefined variable in stack slot[{}]", stack_slot),
                                 ));
-                                break InterpretResult::RuntimeError;
                             }
                         }
                     }
-                    OpCode::SetLocal => {
-                        let index: usize = self.read_byte() as usize;
-                        let local_slot: usize = self.curr_chunk().read_variable(index).clone();
+                    OpCode::SetLocal | OpCode::SetLocalLong => {
+                        let index: usize = Self::read_index(&chunk, &mut ip, &instruction);
+                        let local_slot: usize = chunk.read_variable(index).clone();
                         let stack_slot = local_slot + self.curr_frame().slot;
                         match self.stack.get(stack_slot) {
                             Some(_) => {
@@ -235,42 +414,209 @@ impl VM {
                                 self.stack[stack_slot] = value.clone();
                             }
                             None => {
-                                self.runtime_error(&format!(
-                                    "Undefined variable in stack slot[{}]",
-                                    stack_slot
+                                break InterpretResult::RuntimeError(self.runtime_error_at(
+                                    ip,
+                                    &format!("Undefined variable in stack slot[{}]", stack_slot),
                                 ));
-                                break InterpretResult::RuntimeError;
                             }
                         }
                     }
                     OpCode::JumpFalse => {
-                        let jump_offset: usize = self.read_short() as usize;
+                        let jump_offset: usize = Self::read_short(&chunk, &mut ip) as usize;
                         let value: &Value = self.stack.last().unwrap();
                         if !value.bool_value() {
-                            self.curr_ip_inc(jump_offset);
+                            ip += jump_offset;
                         }
                     }
                     OpCode::Jump => {
-                        let jump_offset: usize = self.read_short() as usize;
-                        self.curr_ip_inc(jump_offset);
+                        let jump_offset: usize = Self::read_short(&chunk, &mut ip) as usize;
+                        ip += jump_offset;
                     }
                     OpCode::JumpBack => {
-                        let jump_offset: usize = self.read_short() as usize;
-                        self.curr_ip_dec(jump_offset);
+                        let jump_offset: usize = Self::read_short(&chunk, &mut ip) as usize;
+                        ip -= jump_offset;
+                    }
+                    OpCode::JumpFalseWide => {
+                        let jump_offset: usize = Self::read_int(&chunk, &mut ip) as usize;
+                        let value: &Value = self.stack.last().unwrap();
+                        if !value.bool_value() {
+                            ip += jump_offset;
+                        }
+                    }
+                    OpCode::JumpWide => {
+                        let jump_offset: usize = Self::read_int(&chunk, &mut ip) as usize;
+                        ip += jump_offset;
+                    }
+                    OpCode::JumpBackWide => {
+                        let jump_offset: usize = Self::read_int(&chunk, &mut ip) as usize;
+                        ip -= jump_offset;
                     }
                     OpCode::Call => {
-                        let arg_cout: usize = self.read_byte() as usize;
-                        let function_value =
-                            self.stack.get(self.stack.len() - 1 - arg_cout).unwrap();
-                        match function_value {
-                            Value::Function(fun) => {
-                                self.frames.push(CallFrame {
-                                    function: fun.clone(),
-                                    ip: RefCell::new(0),
-                                    slot: self.stack.len() - arg_cout,
+                        let arg_cout: usize = Self::read_byte(&chunk, &mut ip) as usize;
+                        self.frames.last_mut().unwrap().ip = ip;
+                        if let Err(error) = self.call_value(arg_cout) {
+                            break InterpretResult::RuntimeError(error);
+                        }
+                        // natives finish in place, closures switch to the new frame
+                        chunk = self.curr_chunk();
+                        ip = self.curr_frame().ip;
+                    }
+                    OpCode::GetUpvalue => {
+                        let index: usize = Self::read_byte(&chunk, &mut ip) as usize;
+                        let upvalue = self.curr_frame().closure.upvalues[index].clone();
+                        let value: Result<Value, usize> = match &*upvalue.borrow() {
+                            Upvalue::Open(stack_slot) => {
+                                self.stack.get(*stack_slot).cloned().ok_or(*stack_slot)
+                            }
+                            Upvalue::Closed(v) => Ok(v.clone()),
+                        };
+                        match value {
+                            Ok(value) => self.stack.push(value),
+                            Err(stack_slot) => {
+                                break InterpretResult::RuntimeError(
+                                    self.runtime_error_at(ip, &past_the_stack(stack_slot)),
+                                );
+                            }
+                        }
+                    }
+                    OpCode::SetUpvalue => {
+                        let index: usize = Self::read_byte(&chunk, &mut ip) as usize;
+                        let upvalue = self.curr_frame().closure.upvalues[index].clone();
+                        let value: Value = self.stack.last().unwrap().clone();
+                        let mut upvalue_ref = upvalue.borrow_mut();
+                        let target: Result<&mut Value, usize> = match &mut *upvalue_ref {
+                            Upvalue::Open(stack_slot) => {
+                                self.stack.get_mut(*stack_slot).ok_or(*stack_slot)
+                            }
+                            Upvalue::Closed(v) => Ok(v),
+                        };
+                        match target {
+                            Ok(target) => *target = value,
+                            Err(stack_slot) => {
+                                break InterpretResult::RuntimeError(
+                                    self.runtime_error_at(ip, &past_the_stack(stack_slot)),
+                                );
+                            }
+                        }
+                    }
+                    OpCode::CloseUpvalue => {
+                        if let Err(message) = self.close_upvalues(self.stack.len() - 1) {
+                            break InterpretResult::RuntimeError(
+                                self.runtime_error_at(ip, &message),
+                            );
+                        }
+                        let _ = self.stack.pop().unwrap();
+                    }
+                    OpCode::Class | OpCode::ClassLong => {
+                        let index: usize = Self::read_index(&chunk, &mut ip, &instruction);
+                        let name: Rc<str> = chunk.read_string(index).clone();
+                        let class = Rc::new(Class::new(name.to_string()));
+                        self.stack.push(Value::Class(class.clone()));
+                        self.track(Object::Class(class));
+                    }
+                    OpCode::GetProperty | OpCode::GetPropertyLong => {
+                        let index: usize = Self::read_index(&chunk, &mut ip, &instruction);
+                        let name: Rc<str> = chunk.read_string(index).clone();
+                        let instance: Rc<Instance> = match self.stack.last().unwrap() {
+                            Value::Instance(instance) => instance.clone(),
+                            _ => {
+                                break InterpretResult::RuntimeError(
+                                    self.runtime_error_at(ip, "Only instances have properties"),
+                                );
+                            }
+                        };
+                        let field = instance.fields.borrow().get(&name).cloned();
+                        let method = instance.class.methods.borrow().get(&name).cloned();
+                        match (field, method) {
+                            (Some(v), _) => *self.stack.last_mut().unwrap() = v,
+                            (None, Some(method)) => {
+                                let bound_method = Rc::new(BoundMethod {
+                                    receiver: Value::Instance(instance),
+                                    method,
                                 });
+                                *self.stack.last_mut().unwrap() =
+                                    Value::BoundMethod(bound_method.clone());
+                                self.track(Object::BoundMethod(bound_method));
+                            }
+                            (None, None) => {
+                                break InterpretResult::RuntimeError(self.runtime_error_at(
+                                    ip,
+                                    &format!("Undefined property '{}'", name),
+                                ));
+                            }
+                        }
+                    }
+                    OpCode::SetProperty | OpCode::SetPropertyLong => {
+                        let index: usize = Self::read_index(&chunk, &mut ip, &instruction);
+                        let name: Rc<str> = chunk.read_string(index).clone();
+                        let value: Value = self.stack.pop().unwrap();
+                        match self.stack.pop().unwrap() {
+                            Value::Instance(instance) => {
+                                instance.fields.borrow_mut().insert(name, value.clone());
+                                self.stack.push(value);
+                            }
+                            _ => {
+                                break InterpretResult::RuntimeError(
+                                    self.runtime_error_at(ip, "Only instances have fields"),
+                                );
+                            }
+                        }
+                    }
+                    OpCode::Method | OpCode::MethodLong => {
+                        let index: usize = Self::read_index(&chunk, &mut ip, &instruction);
+                        let name: Rc<str> = chunk.read_string(index).clone();
+                        let method: Value = self.stack.pop().unwrap();
+                        match (self.stack.last().unwrap(), method) {
+                            (Value::Class(class), Value::Closure(closure)) => {
+                                class.methods.borrow_mut().insert(name, closure);
+                            }
+                            _ => {
+                                break InterpretResult::RuntimeError(
+                                    self.runtime_error_at(ip, "Method define error"),
+                                );
+                            }
+                        }
+                    }
+                    OpCode::Inherit => {
+                        let subclass: Value = self.stack.pop().unwrap();
+                        match (self.stack.last().unwrap(), subclass) {
+                            (Value::Class(superclass), Value::Class(subclass)) => {
+                                let methods = superclass.methods.borrow();
+                                subclass
+                                    .methods
+                                    .borrow_mut()
+                                    .extend(methods.iter().map(|(k, v)| (k.clone(), v.clone())));
+                            }
+                            _ => {
+                                break InterpretResult::RuntimeError(
+                                    self.runtime_error_at(ip, "Superclass must be a class"),
+                                );
+                            }
+                        }
+                    }
+                    OpCode::GetSuper | OpCode::GetSuperLong => {
+                        let index: usize = Self::read_index(&chunk, &mut ip, &instruction);
+                        let name: Rc<str> = chunk.read_string(index).clone();
+                        let superclass: Value = self.stack.pop().unwrap();
+                        let receiver: Value = self.stack.pop().unwrap();
+                        let method = match &superclass {
+                            Value::Class(superclass) => {
+                                superclass.methods.borrow().get(&name).cloned()
+                            }
+                            _ => None,
+                        };
+                        match method {
+                            Some(method) => {
+                                let bound_method = Rc::new(BoundMethod { receiver, method });
+                                self.stack.push(Value::BoundMethod(bound_method.clone()));
+                                self.track(Object::BoundMethod(bound_method));
+                            }
+                            None => {
+                                break InterpretResult::RuntimeError(self.runtime_error_at(
+                                    ip,
+                                    &format!("Undefined property '{}'", name),
+                                ));
                             }
-                            _ => break InterpretResult::RuntimeError,
                         }
                     }
                 }
@@ -284,40 +630,187 @@ impl VM {
     }
 
     fn curr_chunk(&self) -> Rc<Chunk> {
-        self.curr_frame().function.chunk.clone()
+        self.curr_frame().closure.function.chunk.clone()
     }
 
-    fn curr_ip(&self) -> usize {
-        *self.curr_frame().ip.borrow()
+    fn read_byte(chunk: &Chunk, ip: &mut usize) -> u8 {
+        let byte: u8 = chunk.read_code(*ip);
+        *ip += 1;
+        byte
     }
 
-    fn curr_ip_inc(&self, amount: usize) {
-        *self.curr_frame().ip.borrow_mut() += amount;
+    fn read_index(chunk: &Chunk, ip: &mut usize, instruction: &OpCode) -> usize {
+        match instruction.is_long() {
+            true => {
+                let low: usize = Self::read_byte(chunk, ip) as usize;
+                let middle: usize = Self::read_byte(chunk, ip) as usize;
+                let high: usize = Self::read_byte(chunk, ip) as usize;
+                low | (middle << 8) | (high << 16)
+            }
+            false => Self::read_byte(chunk, ip) as usize,
+        }
     }
 
-    fn curr_ip_dec(&self, amount: usize) {
-        *self.curr_frame().ip.borrow_mut() -= amount;
+    fn read_short(chunk: &Chunk, ip: &mut usize) -> u16 {
+        let low: u16 = chunk.read_code(*ip).into();
+        let high: u16 = chunk.read_code(*ip + 1).into();
+        *ip += 2;
+        low | (high << 8)
     }
 
-    fn read_byte(&mut self) -> u8 {
-        let byte: u8 = self.curr_chunk().read_code(self.curr_ip());
-        self.curr_ip_inc(1);
-        byte
+    fn read_int(chunk: &Chunk, ip: &mut usize) -> u32 {
+        let low: u32 = Self::read_short(chunk, ip).into();
+        let high: u32 = Self::read_short(chunk, ip).into();
+        low | (high << 16)
     }
 
-    fn read_short(&mut self) -> u16 {
-        let low: u16 = self.curr_chunk().read_code(self.curr_ip()).into();
-        let high: u16 = self.curr_chunk().read_code(self.curr_ip() + 1).into();
-        self.curr_ip_inc(2);
-        low | (high << 8)
+    fn call_value(&mut self, arg_cout: usize) -> Result<(), RuntimeError> {
+        let callee_slot: usize = self.stack.len() - 1 - arg_cout;
+        match self.stack[callee_slot].clone() {
+            Value::Closure(closure) => self.call_closure(closure, arg_cout)?,
+            Value::Class(class) => {
+                let initializer = class.methods.borrow().get("init").cloned();
+                let instance = Rc::new(Instance::new(class));
+                self.stack[callee_slot] = Value::Instance(instance.clone());
+                self.track(Object::Instance(instance));
+                match initializer {
+                    Some(initializer) => self.call_closure(initializer, arg_cout)?,
+                    None if arg_cout != 0 => {
+                        return Err(self
+                            .runtime_error(&format!("Expected 0 arguments but got {}", arg_cout)));
+                    }
+                    None => {}
+                }
+            }
+            Value::BoundMethod(bound_method) => {
+                self.stack[callee_slot] = bound_method.receiver.clone();
+                self.call_closure(bound_method.method.clone(), arg_cout)?;
+            }
+            Value::Native(native) => {
+                if native.arity != arg_cout {
+                    return Err(self.runtime_error(&format!(
+                        "Expected {} arguments but got {}",
+                        native.arity, arg_cout
+                    )));
+                }
+                let args: Vec<Value> = self.stack.split_off(callee_slot + 1);
+                match (native.function)(self, &args) {
+                    Ok(result) => {
+                        let result: Value = self.intern_value(result);
+                        self.stack.truncate(callee_slot);
+                        self.stack.push(result);
+                    }
+                    Err(msg) => return Err(self.runtime_error(&msg)),
+                }
+            }
+            _ => return Err(self.runtime_error("Can only call functions and classes")),
+        }
+        Ok(())
     }
 
-    fn runtime_error(&mut self, message: &str) {
-        eprintln!(
-            "{} : [line {}] in script",
-            message,
-            self.curr_chunk().read_line(self.curr_ip() - 1).clone()
-        );
-        self.reset_stack()
+    fn call_closure(&mut self, closure: Rc<Closure>, arg_cout: usize) -> Result<(), RuntimeError> {
+        if closure.function.params_num != arg_cout {
+            return Err(self.runtime_error(&format!(
+                "Expected {} arguments but got {}",
+                closure.function.params_num, arg_cout
+            )));
+        }
+        if self.frames.len() >= self.max_frames || self.stack.len() > self.max_stack {
+            return Err(self.runtime_error("Stack overflow"));
+        }
+        self.frames.push(CallFrame {
+            closure,
+            ip: 0,
+            slot: self.stack.len() - arg_cout - 1,
+        });
+        Ok(())
+    }
+
+    fn capture_upvalue(&mut self, stack_slot: usize) -> Rc<RefCell<Upvalue>> {
+        let mut insert_index: usize = self.open_upvalues.len();
+        for (i, upvalue) in self.open_upvalues.iter().enumerate().rev() {
+            match *upvalue.borrow() {
+                Upvalue::Open(slot) if slot == stack_slot => return upvalue.clone(),
+                Upvalue::Open(slot) if slot < stack_slot => break,
+                _ => insert_index = i,
+            }
+        }
+        let upvalue = Rc::new(RefCell::new(Upvalue::Open(stack_slot)));
+        self.open_upvalues.insert(insert_index, upvalue.clone());
+        self.track(Object::Upvalue(upvalue.clone()));
+        upvalue
+    }
+
+    // an upvalue whose slot is already gone still leaves the open list, closed
+    // over nil, so one bad capture can't fail every later close
+    fn close_upvalues(&mut self, last_slot: usize) -> Result<(), String> {
+        while let Some(upvalue) = self.open_upvalues.last() {
+            let stack_slot: usize = match *upvalue.borrow() {
+                Upvalue::Open(slot) => slot,
+                Upvalue::Closed(_) => unreachable!("Closed upvalue in open list"),
+            };
+            if stack_slot < last_slot {
+                break;
+            }
+            let value: Option<Value> = self.stack.get(stack_slot).cloned();
+            let missing: bool = value.is_none();
+            upvalue.replace(Upvalue::Closed(value.unwrap_or(Value::Nil)));
+            self.open_upvalues.pop();
+            if missing {
+                return Err(past_the_stack(stack_slot));
+            }
+        }
+        Ok(())
+    }
+
+    fn track(&mut self, object: Object) {
+        self.heap.track(&object);
+        if self.heap.should_collect() {
+            self.collect_garbage();
+        }
+    }
+
+    // strings made by host code join the intern table before reaching scripts
+    fn intern_value(&mut self, value: Value) -> Value {
+        match value {
+            Value::String(string) => Value::String(self.interner.borrow_mut().intern_rc(string)),
+            _ => value,
+        }
+    }
+
+    // errors raised inside run, where the live ip is not yet in the frame
+    fn runtime_error_at(&mut self, ip: usize, message: &str) -> RuntimeError {
+        self.frames.last_mut().unwrap().ip = ip;
+        self.runtime_error(message)
+    }
+
+    fn runtime_error(&mut self, message: &str) -> RuntimeError {
+        // innermost frame first; each ip already points past its current instruction
+        let trace: Vec<TraceFrame> = self
+            .frames
+            .iter()
+            .rev()
+            .map(|frame| TraceFrame {
+                function: frame.closure.function.to_string(),
+                line: frame.closure.function.chunk.read_line(frame.ip - 1).clone(),
+            })
+            .collect();
+        RuntimeError {
+            message: message.to_string(),
+            trace,
+        }
+    }
+}
+
+// only reachable from unverified bytecode, the verifier rejects code that
+// drops a captured slot without closing it
+fn past_the_stack(stack_slot: usize) -> String {
+    format!("Upvalue refers to stack slot {} past the stack", stack_slot)
+}
+
+fn clock_native(_vm: &mut VM, _args: &[Value]) -> Result<Value, String> {
+    match std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH) {
+        Ok(duration) => Ok(Value::Number(duration.as_secs_f64())),
+        Err(e) => Err(e.to_string()),
     }
 }
@@ -0,0 +1,148 @@
+mod common;
+
+use common::compile;
+use rlox::{Function, InterpretResult, Value, VM};
+
+const SOURCE: &str = "class Shape { init(n) { this.n = n; } sides() { return this.n; } }\n\
+                      class Square < Shape { init() { super.init(4); } }\n\
+                      fun counter() { var c = 0; fun inc() { c = c + 1; return c; } return inc; }\n\
+                      var next = counter();\n\
+                      fun result() { next(); return Square().sides() * 10 + next() + 0.5; }\n\
+                      fun label() { return \"sq\" + \"uare\"; }";
+
+fn result(vm: &mut VM) -> f64 {
+    common::number(vm, "result")
+}
+
+#[test]
+fn round_trip_is_stable() {
+    let bytes = compile(SOURCE).to_bytes();
+    assert_eq!(&bytes[..4], b"RLOX");
+    let loaded = Function::from_bytes(&bytes).unwrap();
+    assert_eq!(loaded.to_bytes(), bytes);
+}
+
+#[test]
+fn loaded_script_runs_like_source() {
+    let mut source_vm = VM::new();
+    assert!(matches!(
+        source_vm.interpret_source(SOURCE),
+        InterpretResult::Success
+    ));
+
+    let mut bytes_vm = VM::new();
+    let bytes = compile(SOURCE).to_bytes();
+    assert!(matches!(
+        bytes_vm.interpret_bytes(&bytes),
+        InterpretResult::Success
+    ));
+
+    assert_eq!(result(&mut source_vm), 42.5);
+    assert_eq!(result(&mut bytes_vm), 42.5);
+    match bytes_vm.call_global("label", &[]) {
+        Ok(Value::String(s)) => assert_eq!(&*s, "square"),
+        _ => panic!("expected string"),
+    }
+}
+
+#[test]
+fn large_pools_and_wide_jumps_round_trip() {
+    let mut source = String::new();
+    for i in 0..300 {
+        source.push_str(&format!("var g{} = \"s{}\";\n", i, i));
+    }
+    source.push_str("fun result() { var n = 0; while (n < 2) {\n");
+    for _ in 0..9000 {
+        source.push_str("n = n + 1;\n");
+    }
+    source.push_str("} if (g299 == \"s299\") return n; return -1; }\n");
+    let bytes = compile(&source).to_bytes();
+    assert_eq!(Function::from_bytes(&bytes).unwrap().to_bytes(), bytes);
+    let mut vm = VM::new();
+    assert!(matches!(
+        vm.interpret_bytes(&bytes),
+        InterpretResult::Success
+    ));
+    assert_eq!(result(&mut vm), 9000.0);
+}
+
+fn load_error(bytes: &[u8]) -> String {
+    match Function::from_bytes(bytes) {
+        Ok(_) => panic!("expected load error"),
+        Err(message) => message,
+    }
+}
+
+#[test]
+fn malformed_files_are_rejected() {
+    let bytes = compile(SOURCE).to_bytes();
+    assert_eq!(load_error(b"print 1;"), "Not an rlox bytecode file");
+
+    let mut wrong_version = bytes.clone();
+    wrong_version[4] = 99;
+    assert_eq!(
+        load_error(&wrong_version),
+        "Unsupported bytecode version 99, expected 1"
+    );
+
+    assert!(load_error(&bytes[..bytes.len() - 3]).starts_with("Unexpected end of bytecode"));
+
+    let mut trailing = bytes.clone();
+    trailing.push(0);
+    assert!(load_error(&trailing).starts_with("Trailing bytes"));
+
+    let mut vm = VM::new();
+    assert!(matches!(
+        vm.interpret_bytes(b"RLO"),
+        InterpretResult::LoadError(_)
+    ));
+}
+
+// a script holding a chain of empty functions, each nested in the one before
+fn nested_file(depth: usize) -> Vec<u8> {
+    let mut bytes: Vec<u8> = b"RLOX".to_vec();
+    bytes.extend_from_slice(&1u16.to_le_bytes());
+    for level in 0..depth {
+        let nested: u32 = match level + 1 < depth {
+            true => 1,
+            false => 0,
+        };
+        // name, params_num, upvalue_count, code: nil return, lines
+        for field in [0u32, 0, 0, 2] {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+        bytes.extend_from_slice(&[1, 0]);
+        for field in [2u32, 1, 1] {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+        // numbers, strings, variables, functions
+        for field in [0u32, 0, 0, nested] {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+    }
+    bytes
+}
+
+#[test]
+fn deep_nesting_is_rejected() {
+    assert!(Function::from_bytes(&nested_file(256)).is_ok());
+    assert_eq!(
+        load_error(&nested_file(257)),
+        "Functions nested too deeply at byte 11782, the limit is 256"
+    );
+    // far past the limit the loader still fails cleanly instead of overflowing
+    assert!(load_error(&nested_file(200_000)).starts_with("Functions nested too deeply"));
+}
+
+#[test]
+fn verifier_limits_nesting() {
+    let depth: usize = 300;
+    let source: String = (0..depth)
+        .map(|i| format!("fun f{}() {{ ", i))
+        .collect::<String>()
+        + &"} ".repeat(depth);
+    assert_eq!(
+        compile(&source).verify().unwrap_err(),
+        "Invalid bytecode in <fn f255>: functions nested too deeply, the limit is 256"
+    );
+}
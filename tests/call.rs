@@ -0,0 +1,129 @@
+mod common;
+
+use common::runtime_error;
+use rlox::{Value, VM};
+
+#[test]
+fn function_with_too_few_arguments() {
+    let message = runtime_error("fun f(a, b) { return a + b; } f(1);").message;
+    assert_eq!(message, "Expected 2 arguments but got 1");
+}
+
+#[test]
+fn function_with_too_many_arguments() {
+    let message = runtime_error("fun f(a) { return a; } f(1, 2, 3);").message;
+    assert_eq!(message, "Expected 1 arguments but got 3");
+}
+
+#[test]
+fn method_and_initializer_arity() {
+    let message = runtime_error("class A { m(x) {} } A().m();").message;
+    assert_eq!(message, "Expected 1 arguments but got 0");
+
+    let message = runtime_error("class A { init(x, y) {} } A(1);").message;
+    assert_eq!(message, "Expected 2 arguments but got 1");
+
+    let message = runtime_error("class A {} A(1);").message;
+    assert_eq!(message, "Expected 0 arguments but got 1");
+}
+
+#[test]
+fn native_arity() {
+    let message = runtime_error("clock(1);").message;
+    assert_eq!(message, "Expected 0 arguments but got 1");
+}
+
+#[test]
+fn call_non_callable() {
+    for source in [
+        "1();",
+        "\"str\"();",
+        "nil();",
+        "true();",
+        "class A {} A()();",
+    ] {
+        assert_eq!(
+            runtime_error(source).message,
+            "Can only call functions and classes"
+        );
+    }
+}
+
+#[test]
+fn call_error_keeps_trace() {
+    let error = runtime_error("fun f(a) { return a; }\nfun g() {\n  return f();\n}\ng();");
+    let trace: Vec<String> = error.trace.iter().map(|frame| frame.to_string()).collect();
+    assert_eq!(trace, vec!["[line 3] in <fn g>", "[line 5] in <script>"]);
+}
+
+#[test]
+fn trace_goes_through_methods_and_closures() {
+    let source = "class A {\n  m() {\n    fun inner() {\n      return nil + 1;\n    }\n    return inner();\n  }\n}\nA().m();";
+    let trace: Vec<String> = runtime_error(source)
+        .trace
+        .iter()
+        .map(|frame| frame.to_string())
+        .collect();
+    assert_eq!(
+        trace,
+        vec![
+            "[line 4] in <fn inner>",
+            "[line 6] in <fn m>",
+            "[line 9] in <script>"
+        ]
+    );
+}
+
+#[test]
+fn call_global_trace_stops_at_the_host() {
+    let mut vm = common::run("fun f() {\n  return -nil;\n}\nfun g() {\n  return f();\n}");
+    match vm.call_global("g", &[]) {
+        Err(error) => {
+            let trace: Vec<String> = error.trace.iter().map(|frame| frame.to_string()).collect();
+            assert_eq!(trace, vec!["[line 2] in <fn f>", "[line 5] in <fn g>"]);
+        }
+        Ok(value) => panic!("expected runtime error, got {}", value.to_string()),
+    }
+}
+
+#[test]
+fn matching_arity_succeeds() {
+    let source = "fun add(a, b) { return a + b; } class P { init(x) { this.x = x; } }";
+    let mut vm = common::run(source);
+    let result = vm.call_global("add", &[Value::Number(1.0), Value::Number(2.0)]);
+    assert!(matches!(result, Ok(Value::Number(n)) if n == 3.0));
+    assert!(vm.call_global("add", &[Value::Number(1.0)]).is_err());
+}
+
+// swallows the error from a script function it calls back into
+fn try_fail(vm: &mut VM, _args: &[Value]) -> Result<Value, String> {
+    match vm.call_global("fail", &[]) {
+        Ok(value) => Ok(value),
+        Err(error) => Ok(vm.intern_string(&error.message)),
+    }
+}
+
+#[test]
+fn native_survives_failing_callback() {
+    let mut vm = VM::new();
+    vm.define_native("try_fail", 0, try_fail);
+    let source = "fun fail() { var local = 1; return local + nil; }\n\
+                  fun outer() { var a = 2; fun get() { return a; } var m = try_fail(); if (get() == a) return m; return nil; }\n\
+                  var result = outer();";
+    common::run_in(&mut vm, source);
+    assert_eq!(
+        &*common::string(vm.call_global("outer", &[])),
+        "Add operation error"
+    );
+}
+
+#[test]
+fn results_can_be_unwrapped() {
+    let mut vm = common::run("class Node {}\nfun make() { var n = Node(); n.me = n; return n; }");
+    // the instance points at itself, debug output must not follow its fields
+    let node: Value = vm.call_global("make", &[]).unwrap();
+    assert_eq!(format!("{:?}", node), "Instance(<Node instance>)");
+    let error = vm.call_global("missing", &[]).unwrap_err();
+    assert_eq!(error.message, "Undefined variable 'missing'");
+    assert_eq!(format!("{:?}", vm.interpret_source("1;")), "Success");
+}
@@ -0,0 +1,80 @@
+mod common;
+
+use common::call_number;
+
+#[test]
+fn methods_see_this() {
+    let source = "class Point { init(x, y) { this.x = x; this.y = y; } sum() { return this.x + this.y; } }\n\
+                  fun run() { return Point(3, 4).sum(); }";
+    assert_eq!(call_number(source, "run"), 7.0);
+}
+
+#[test]
+fn bound_method_keeps_its_receiver() {
+    let source = "class Box { init(v) { this.v = v; } get() { return this.v; } }\n\
+                  var get = Box(9).get;\n\
+                  fun run() { return get(); }";
+    assert_eq!(call_number(source, "run"), 9.0);
+}
+
+#[test]
+fn closure_in_a_method_captures_this() {
+    let source = "class Counter { init() { this.n = 0; } adder() { fun add() { this.n = this.n + 1; return this.n; } return add; } }\n\
+                  fun run() { var c = Counter(); var add = c.adder(); add(); add(); return c.n; }";
+    assert_eq!(call_number(source, "run"), 2.0);
+}
+
+#[test]
+fn initializer_returns_the_instance() {
+    let source = "class A { init() { this.v = 1; return; } }\n\
+                  fun run() { var a = A(); return a.init().v; }";
+    assert_eq!(call_number(source, "run"), 1.0);
+}
+
+#[test]
+fn super_calls_the_overridden_method() {
+    let source = "class A { name() { return 1; } }\n\
+                  class B < A { name() { return super.name() + 10; } }\n\
+                  fun run() { return B().name(); }";
+    assert_eq!(call_number(source, "run"), 11.0);
+}
+
+#[test]
+fn super_is_resolved_from_the_defining_class() {
+    // C inherits B.name, whose super still refers to A and not to B
+    let source = "class A { name() { return 1; } }\n\
+                  class B < A { name() { return super.name() + 10; } }\n\
+                  class C < B {}\n\
+                  fun run() { return C().name(); }";
+    assert_eq!(call_number(source, "run"), 11.0);
+}
+
+#[test]
+fn bound_super_method_keeps_this() {
+    let source = "class A { init() { this.v = 5; } get() { return this.v; } }\n\
+                  class B < A { get() { var m = super.get; return m() * 2; } }\n\
+                  fun run() { return B().get(); }";
+    assert_eq!(call_number(source, "run"), 10.0);
+}
+
+#[test]
+fn subclass_inherits_the_initializer() {
+    let source = "class A { init(v) { this.v = v; } }\n\
+                  class B < A {}\n\
+                  fun run() { return B(4).v; }";
+    assert_eq!(call_number(source, "run"), 4.0);
+}
+
+#[test]
+fn instances_compare_with_nil() {
+    let source = "class Node { init(v, next) { this.v = v; this.next = next; } }\n\
+                  fun run() {\n\
+                    var list = Node(1, Node(2, Node(3, nil)));\n\
+                    var sum = 0;\n\
+                    for (var node = list; node != nil; node = node.next) sum = sum + node.v;\n\
+                    if (list.next.next.next == nil) sum = sum * 10;\n\
+                    if (list == 1 or list == \"a\" or nil == list) sum = 0;\n\
+                    return sum;\n\
+                  }";
+    assert_eq!(call_number(source, "run"), 60.0);
+}
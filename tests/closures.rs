@@ -0,0 +1,58 @@
+mod common;
+
+use common::call_number;
+
+#[test]
+fn closure_keeps_its_own_counter() {
+    let source = "fun counter() { var n = 0; fun inc() { n = n + 1; return n; } return inc; }\n\
+                  var a = counter();\nvar b = counter();\n\
+                  fun run() { a(); a(); b(); return a() * 10 + b(); }";
+    assert_eq!(call_number(source, "run"), 32.0);
+}
+
+#[test]
+fn closures_share_a_captured_variable() {
+    let source = "var get;\nvar set;\n\
+                  { var x = 1; fun g() { return x; } fun s(v) { x = v; } get = g; set = s; }\n\
+                  fun run() { set(7); return get(); }";
+    assert_eq!(call_number(source, "run"), 7.0);
+}
+
+#[test]
+fn block_exit_closes_each_local() {
+    let source = "var first;\nvar second;\n\
+                  for (var i = 1; i < 3; i = i + 1) { var j = i * 10; fun f() { return j; } if (i == 1) first = f; else second = f; }\n\
+                  fun run() { return first() + second(); }";
+    assert_eq!(call_number(source, "run"), 30.0);
+}
+
+#[test]
+fn capture_through_an_enclosing_function() {
+    let source = "fun outer() { var x = 1; fun middle() { fun inner() { x = x + 1; return x; } return inner; } return middle(); }\n\
+                  fun run() { var f = outer(); f(); return f(); }";
+    assert_eq!(call_number(source, "run"), 3.0);
+}
+
+#[test]
+fn open_upvalue_sees_later_assignments() {
+    let source = "fun run() { var x = 1; fun get() { return x; } x = 5; return get(); }";
+    assert_eq!(call_number(source, "run"), 5.0);
+}
+
+#[test]
+fn functions_declared_in_blocks_are_local() {
+    let source = "fun run() {\n\
+                    var n = 0;\n\
+                    { fun add(x) { return x + 1; } n = add(n); }\n\
+                    if (true) { fun add(x) { return x + 10; } n = add(n); }\n\
+                    fun add(x) { return x + 100; }\n\
+                    return add(n);\n\
+                  }";
+    assert_eq!(call_number(source, "run"), 111.0);
+}
+
+#[test]
+fn local_function_can_recurse() {
+    let source = "fun run() { fun fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); } return fib(10); }";
+    assert_eq!(call_number(source, "run"), 55.0);
+}
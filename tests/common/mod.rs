@@ -0,0 +1,87 @@
+// helpers shared by the integration tests, each test file uses only some
+#![allow(dead_code)]
+
+use rlox::{CompileError, Compiler, Function, InterpretResult, RuntimeError, Value, VM};
+use std::rc::Rc;
+
+pub fn run_in(vm: &mut VM, source: &str) {
+    match vm.interpret_source(source) {
+        InterpretResult::Success => {}
+        result => panic!("{}", result.to_string()),
+    }
+}
+
+pub fn run(source: &str) -> VM {
+    let mut vm = VM::new();
+    run_in(&mut vm, source);
+    vm
+}
+
+// call a script function that takes no arguments and returns a number
+pub fn number(vm: &mut VM, name: &str) -> f64 {
+    match vm.call_global(name, &[]) {
+        Ok(Value::Number(n)) => n,
+        Ok(value) => panic!("expected number, got {}", value.to_string()),
+        Err(error) => panic!("{}", error.to_string()),
+    }
+}
+
+pub fn string(value: Result<Value, RuntimeError>) -> Rc<str> {
+    match value {
+        Ok(Value::String(s)) => s,
+        Ok(value) => panic!("expected string, got {}", value.to_string()),
+        Err(error) => panic!("{}", error.to_string()),
+    }
+}
+
+pub fn boolean(value: Result<Value, RuntimeError>) -> bool {
+    match value {
+        Ok(Value::Bool(b)) => b,
+        Ok(value) => panic!("expected bool, got {}", value.to_string()),
+        Err(error) => panic!("{}", error.to_string()),
+    }
+}
+
+pub fn call_number(source: &str, name: &str) -> f64 {
+    number(&mut run(source), name)
+}
+
+pub fn compile(source: &str) -> Function {
+    match Compiler::new().compile(source) {
+        Ok(function) => function,
+        Err(errors) => panic!(
+            "{}",
+            errors
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<String>>()
+                .join("\n")
+        ),
+    }
+}
+
+pub fn compile_errors(source: &str) -> Vec<CompileError> {
+    match Compiler::new().compile(source) {
+        Ok(_) => panic!("expected compile error"),
+        Err(errors) => errors,
+    }
+}
+
+pub fn runtime_error_in(vm: &mut VM, source: &str) -> RuntimeError {
+    match vm.interpret_source(source) {
+        InterpretResult::RuntimeError(error) => error,
+        result => panic!("expected runtime error, got {}", result.to_string()),
+    }
+}
+
+pub fn runtime_error(source: &str) -> RuntimeError {
+    runtime_error_in(&mut VM::new(), source)
+}
+
+// interpret a bytecode file that should fail to load or verify
+pub fn load_error(bytes: &[u8]) -> String {
+    match VM::new().interpret_bytes(bytes) {
+        InterpretResult::LoadError(message) => message,
+        result => panic!("expected load error, got {}", result.to_string()),
+    }
+}
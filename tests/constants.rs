@@ -0,0 +1,89 @@
+mod common;
+
+use common::{number, run};
+
+#[test]
+fn more_than_256_numbers_and_globals() {
+    let mut source = String::new();
+    for i in 0..600 {
+        source.push_str(&format!("var g{} = {}.5;\n", i, i));
+    }
+    source.push_str("fun last() { return g599; }\n");
+    let mut vm = run(&source);
+    assert_eq!(number(&mut vm, "last"), 599.5);
+}
+
+#[test]
+fn more_than_256_strings_and_properties() {
+    let mut source = String::from("class Bag {}\nvar bag = Bag();\n");
+    for i in 0..400 {
+        source.push_str(&format!("bag.p{} = \"s{}\";\n", i, i));
+    }
+    source.push_str("fun check() { if (bag.p399 == \"s399\") return 1; return 0; }\n");
+    let mut vm = run(&source);
+    assert_eq!(number(&mut vm, "check"), 1.0);
+}
+
+#[test]
+fn more_than_256_local_accesses_and_functions() {
+    let mut source = String::from("fun sum() {\n  var total = 0;\n");
+    for i in 0..300 {
+        source.push_str(&format!("  fun f{}() {{ return {}; }}\n", i, i));
+        source.push_str(&format!("  total = total + f{}();\n", i));
+    }
+    source.push_str("  return total;\n}\n");
+    let mut vm = run(&source);
+    assert_eq!(number(&mut vm, "sum"), (0..300).sum::<i32>() as f64);
+}
+
+fn code_size(source: &str) -> usize {
+    common::compile(source).chunk.code_size()
+}
+
+#[test]
+fn repeated_constants_share_one_index() {
+    // distinct constants past 255 need the long operand form, repeats never do
+    let repeated: String = (0..300).map(|_| "print \"s\" + 1;\n").collect();
+    let distinct: String = (0..300)
+        .map(|i| format!("print \"s{}\" + {};\n", i, i))
+        .collect();
+    assert_eq!(code_size(&repeated), 300 * 6 + 2);
+    assert!(code_size(&distinct) > code_size(&repeated));
+
+    let mut source = String::from("fun count() {\n  var n = 0;\n");
+    for _ in 0..300 {
+        source.push_str("  n = n + 1;\n");
+    }
+    source.push_str("  return n;\n}\n");
+    let mut vm = run(&source);
+    assert_eq!(number(&mut vm, "count"), 300.0);
+}
+
+#[test]
+fn capturing_a_local_past_255() {
+    let mut source = String::from("fun outer() {\n");
+    for i in 0..300 {
+        source.push_str(&format!("  var v{} = {};\n", i, i));
+    }
+    source.push_str("  fun inner() { return v299 + v10; }\n  return inner;\n}\n");
+    let messages: Vec<String> = common::compile_errors(&source)
+        .iter()
+        .map(|e| e.to_string())
+        .collect();
+    assert_eq!(
+        messages,
+        vec!["[line 302:24] Error at 'v299' : Can't capture local slot past 255"]
+    );
+}
+
+#[test]
+fn capturing_a_local_up_to_255() {
+    let mut source = String::from("fun outer() {\n");
+    for i in 0..254 {
+        source.push_str(&format!("  var v{} = {};\n", i, i));
+    }
+    source.push_str("  fun inner() { return v253; }\n  return inner;\n}\n");
+    source.push_str("var f = outer();\nfun last() { return f(); }\n");
+    let mut vm = run(&source);
+    assert_eq!(number(&mut vm, "last"), 253.0);
+}
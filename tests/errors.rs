@@ -0,0 +1,83 @@
+mod common;
+
+use common::compile_errors;
+
+fn messages(source: &str) -> Vec<String> {
+    compile_errors(source)
+        .iter()
+        .map(|error| error.to_string())
+        .collect()
+}
+
+#[test]
+fn return_value_from_initializer() {
+    assert_eq!(
+        messages("class A {\n  init() { return 1; }\n}\nprint A;"),
+        vec!["[line 2:12] Error at 'return' : Can't return a value from an initializer"]
+    );
+}
+
+#[test]
+fn recovery_stops_at_the_next_statement() {
+    assert_eq!(
+        messages("print 1 print 2;\nvar ;\nprint 3;"),
+        vec![
+            "[line 1:9] Error at 'print' : Expect ';' after print value",
+            "[line 2:5] Error at ';' : Expect variable error",
+        ]
+    );
+}
+
+#[test]
+fn super_outside_of_a_class() {
+    assert_eq!(
+        messages("fun f() {\n  return super.m();\n}\nprint f;"),
+        vec!["[line 2:10] Error at 'super' : Can't use 'super' outside of a class"]
+    );
+}
+
+#[test]
+fn super_without_a_superclass() {
+    assert_eq!(
+        messages("class A {\n  m() { return super.m(); }\n}\nprint A;"),
+        vec!["[line 2:16] Error at 'super' : Can't use 'super' in a class with no superclass"]
+    );
+}
+
+#[test]
+fn error_position_is_the_token_start() {
+    let errors = compile_errors("var a = 1;\n  var b = );\nvar c = 3;");
+    assert_eq!(errors.len(), 1);
+    assert_eq!((errors[0].line, errors[0].column), (2, 11));
+    assert_eq!(errors[0].lexeme, ")");
+    assert_eq!(errors[0].message, "Expect prefix error");
+}
+
+#[test]
+fn error_at_end_of_input() {
+    let errors = compile_errors("print 1\n");
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].line, 2);
+    assert_eq!(errors[0].lexeme, "");
+}
+
+#[test]
+fn errors_are_reported_in_source_order() {
+    let errors = compile_errors("var = 1;\nprint 2;\nvar = 3;");
+    let lines: Vec<u32> = errors.iter().map(|error| error.line).collect();
+    assert_eq!(lines, vec![1, 3]);
+}
+
+#[test]
+fn errors_convert_to_std_error() {
+    let error: Box<dyn std::error::Error> = Box::new(compile_errors("var = 1;").remove(0));
+    assert_eq!(
+        error.to_string(),
+        "[line 1:5] Error at '=' : Expect variable error"
+    );
+    let error: Box<dyn std::error::Error> = Box::new(common::runtime_error("print -nil;"));
+    assert_eq!(
+        error.to_string(),
+        "Neg operation error\n[line 1] in <script>"
+    );
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use common::run_in;
+use rlox::{Value, VM};
+use std::rc::Rc;
+
+#[test]
+fn unreachable_cycles_are_collected() {
+    let mut vm = VM::new();
+    let source = "class Node {}\n\
+                  fun pair() { var a = Node(); var b = Node(); a.next = b; b.next = a; }\n\
+                  fun counter() { var n = 0; fun inc() { n = n + 1; return inc; } return inc; }\n\
+                  for (var i = 0; i < 50; i = i + 1) { pair(); counter(); }";
+    run_in(&mut vm, source);
+    let before = vm.heap_size();
+    let freed = vm.collect_garbage();
+    assert!(freed >= 50 * 2);
+    assert_eq!(vm.heap_size(), before - freed);
+}
+
+#[test]
+fn reachable_objects_survive() {
+    let mut vm = VM::new();
+    let source = "class Node {}\n\
+                  var root = Node();\n\
+                  root.next = Node();\n\
+                  root.next.next = root;\n\
+                  root.next.value = \"kept\";\n\
+                  fun value() { return root.next.value; }";
+    run_in(&mut vm, source);
+    vm.collect_garbage();
+    match vm.call_global("value", &[]) {
+        Ok(Value::String(s)) => assert_eq!(&*s, "kept"),
+        _ => panic!("expected string"),
+    }
+}
+
+#[test]
+fn host_handles_keep_objects_alive() {
+    let mut vm = VM::new();
+    run_in(
+        &mut vm,
+        "class Node {}\nfun make() { var n = Node(); n.me = n; n.tag = 7; return n; }",
+    );
+    let node = vm.call_global("make", &[]).unwrap();
+    let weak = match &node {
+        Value::Instance(instance) => Rc::downgrade(instance),
+        _ => panic!("expected instance"),
+    };
+    vm.collect_garbage();
+    assert!(weak.upgrade().unwrap().to_string() == "<Node instance>");
+    // once the host lets go, the self cycle is garbage
+    drop(node);
+    assert!(weak.upgrade().is_some());
+    vm.collect_garbage();
+    assert!(weak.upgrade().is_none());
+}
+
+#[test]
+fn stress_mode_keeps_results() {
+    let mut vm = VM::new();
+    vm.set_gc_stress(true);
+    let source = "class A { init(x) { this.x = x; } get() { return this.x; } }\n\
+                  class B < A { get() { return super.get() + 1; } }\n\
+                  fun make(n) { var total = 0; fun add(v) { total = total + v; return total; } \
+                  for (var i = 0; i < n; i = i + 1) add(B(i).get()); return add(0); }\n\
+                  fun result() { return make(10); }";
+    run_in(&mut vm, source);
+    match vm.call_global("result", &[]) {
+        Ok(Value::Number(n)) => assert_eq!(n, 55.0),
+        _ => panic!("expected number"),
+    }
+}
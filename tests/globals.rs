@@ -0,0 +1,53 @@
+mod common;
+
+use rlox::{InterpretResult, Session, Value};
+
+#[test]
+fn session_can_redefine_globals() {
+    let mut session = Session::new();
+    assert!(matches!(
+        session.eval("var x = 1;"),
+        InterpretResult::Success
+    ));
+    assert!(matches!(
+        session.eval("var x = 5;"),
+        InterpretResult::Success
+    ));
+    assert!(matches!(
+        session.eval("fun f() { return x; }"),
+        InterpretResult::Success
+    ));
+    assert!(matches!(
+        session.eval("fun f() { return x * 2; }"),
+        InterpretResult::Success
+    ));
+    match session.vm().call_global("f", &[]) {
+        Ok(Value::Number(n)) => assert_eq!(n, 10.0),
+        _ => panic!("expected number"),
+    }
+}
+
+#[test]
+fn script_can_shadow_a_native() {
+    let mut vm = common::run("fun clock() { return 42; }");
+    match vm.call_global("clock", &[]) {
+        Ok(Value::Number(n)) => assert_eq!(n, 42.0),
+        _ => panic!("expected number"),
+    }
+}
+
+#[test]
+fn functions_can_use_globals_defined_later() {
+    let source = "fun isEven(n) { if (n == 0) return true; return isOdd(n - 1); }\n\
+                  fun isOdd(n) { if (n == 0) return false; return isEven(n - 1); }\n\
+                  var limit = 10;\n\
+                  fun run() { return isEven(limit) and isOdd(limit + 1); }";
+    let mut vm = common::run(source);
+    assert!(common::boolean(vm.call_global("run", &[])));
+}
+
+#[test]
+fn global_used_before_its_definition_runs() {
+    let error = common::runtime_error("fun f() { return later; }\nprint f();\nvar later = 1;");
+    assert_eq!(error.message, "Undefined variable 'later'");
+}
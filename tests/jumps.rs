@@ -0,0 +1,51 @@
+mod common;
+
+use common::call_number;
+
+// each statement compiles to 8 bytes, so 10000 of them overflow a 16-bit jump
+fn big_body(variable: &str) -> String {
+    (0..10000)
+        .map(|_| format!("{} = {} + 1;\n", variable, variable))
+        .collect()
+}
+
+#[test]
+fn large_while_body() {
+    let source = format!(
+        "fun run() {{\n  var i = 0;\n  var n = 0;\n  while (i < 3) {{\n{}    i = i + 1;\n  }}\n  return n;\n}}\n",
+        big_body("n")
+    );
+    assert_eq!(call_number(&source, "run"), 30000.0);
+}
+
+#[test]
+fn large_for_body() {
+    let source = format!(
+        "fun run() {{\n  var n = 0;\n  for (var i = 0; i < 2; i = i + 1) {{\n{}  }}\n  return n;\n}}\n",
+        big_body("n")
+    );
+    assert_eq!(call_number(&source, "run"), 20000.0);
+}
+
+#[test]
+fn large_if_and_else_bodies() {
+    let source = format!(
+        "fun run(flag) {{\n  var n = 0;\n  if (flag) {{\n{}  }} else {{\n{}    n = n - 1;\n  }}\n  return n;\n}}\n\
+         fun yes() {{ return run(true); }}\nfun no() {{ return run(false); }}\n",
+        big_body("n"),
+        big_body("n")
+    );
+    assert_eq!(call_number(&source, "yes"), 10000.0);
+    assert_eq!(call_number(&source, "no"), 9999.0);
+}
+
+#[test]
+fn short_loops_around_wide_jumps() {
+    // the inner loops stay short while the outer loop needs wide jumps
+    let source = format!(
+        "fun run() {{\n  var n = 0;\n  var i = 0;\n  while (i < 2) {{\n    var j = 0;\n    while (j < 3) {{ j = j + 1; n = n + 1; }}\n\
+         {}    var k = 0;\n    while (k < 4) {{ k = k + 1; n = n + 1; }}\n    i = i + 1;\n  }}\n  return n and n;\n}}\n",
+        big_body("n")
+    );
+    assert_eq!(call_number(&source, "run"), 20014.0);
+}
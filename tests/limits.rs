@@ -0,0 +1,45 @@
+mod common;
+
+use common::runtime_error_in;
+use rlox::{InterpretResult, VM};
+
+const RUNAWAY: &str = "fun f(n) { return f(n + 1); }\nf(0);";
+
+#[test]
+fn runaway_recursion_overflows() {
+    let mut vm = VM::new();
+    let error = runtime_error_in(&mut vm, RUNAWAY);
+    assert_eq!(error.message, "Stack overflow");
+    assert_eq!(error.trace.len(), rlox::FRAMES_MAX);
+    assert_eq!(
+        error.trace.last().unwrap().to_string(),
+        "[line 2] in <script>"
+    );
+    // the vm stays usable after the overflow
+    assert!(matches!(
+        vm.interpret_source("print 1;"),
+        InterpretResult::Success
+    ));
+}
+
+#[test]
+fn configured_limits() {
+    let mut vm = VM::new();
+    vm.set_max_frames(8);
+    assert_eq!(runtime_error_in(&mut vm, RUNAWAY).trace.len(), 8);
+
+    let mut vm = VM::new();
+    vm.set_max_stack(16);
+    let source = "fun f(a, b, c, d) { return f(a, b, c, d); }\nf(1, 2, 3, 4);";
+    let error = runtime_error_in(&mut vm, source);
+    assert_eq!(error.message, "Stack overflow");
+    assert!(error.trace.len() < 8);
+
+    let mut vm = VM::new();
+    vm.set_max_frames(8);
+    let source = "fun f(n) { if (n > 0) return f(n - 1); return n; }\nf(6);";
+    assert!(matches!(
+        vm.interpret_source(source),
+        InterpretResult::Success
+    ));
+}
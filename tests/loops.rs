@@ -0,0 +1,59 @@
+mod common;
+
+use common::call_number;
+
+fn compile_errors(source: &str) -> Vec<String> {
+    common::compile_errors(source)
+        .iter()
+        .map(|e| e.message.clone())
+        .collect()
+}
+
+#[test]
+fn break_leaves_while() {
+    let source = "fun run() { var i = 0; while (true) { var a = i; i = i + 1; if (i == 5) { var b = a; break; } } return i; }";
+    assert_eq!(call_number(source, "run"), 5.0);
+}
+
+#[test]
+fn continue_skips_to_for_increment() {
+    let source = "fun run() { var sum = 0; for (var i = 0; i < 10; i = i + 1) { var half = i / 2; if (i == 3) continue; if (i == 8) break; sum = sum + i; } return sum; }";
+    assert_eq!(
+        call_number(source, "run"),
+        0.0 + 1.0 + 2.0 + 4.0 + 5.0 + 6.0 + 7.0
+    );
+}
+
+#[test]
+fn nested_loops_only_leave_the_innermost() {
+    let source = "fun run() { var n = 0; for (var p = 0; p < 3; p = p + 1) { for (var q = 0; q < 3; q = q + 1) { if (q == 2) break; if (p == 1) continue; n = n + 1; } } return n; }";
+    assert_eq!(call_number(source, "run"), 4.0);
+}
+
+#[test]
+fn break_closes_captured_locals() {
+    let source = "var f; fun run() { for (var k = 0; ; k = k + 1) { var c = k; fun g() { return c; } if (k == 3) { f = g; break; } } return f(); }";
+    assert_eq!(call_number(source, "run"), 3.0);
+}
+
+#[test]
+fn jumps_keep_the_stack_balanced() {
+    let source = "for (var i = 0; i < 3; i = i + 1) { var a = 1; { var b = 2; if (a) continue; if (b) break; } var c = 3; }\nvar j = 0; while (j < 3) { var d = j; j = j + 1; if (d) continue; }";
+    assert!(common::compile(source).verify().is_ok());
+}
+
+#[test]
+fn outside_of_a_loop() {
+    assert_eq!(
+        compile_errors("break;\nfun f() { continue; }"),
+        vec![
+            "Can't use 'break' outside of a loop",
+            "Can't use 'continue' outside of a loop"
+        ]
+    );
+    // a function body starts outside of any loop
+    assert_eq!(
+        compile_errors("while (true) { fun f() { break; } break; }"),
+        vec!["Can't use 'break' outside of a loop"]
+    );
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use rlox::{Value, VM};
+
+fn sum(_vm: &mut VM, args: &[Value]) -> Result<Value, String> {
+    match (&args[0], &args[1]) {
+        (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a + b)),
+        _ => Err("sum takes two numbers".to_string()),
+    }
+}
+
+fn greeting(vm: &mut VM, _args: &[Value]) -> Result<Value, String> {
+    Ok(vm.intern_string("hello"))
+}
+
+#[test]
+fn script_calls_a_native() {
+    let mut vm = VM::new();
+    vm.define_native("sum", 2, sum);
+    vm.define_native("greeting", 0, greeting);
+    common::run_in(
+        &mut vm,
+        "fun run() { return sum(1, 2) * 10; }\nfun text() { return greeting() + \"!\"; }",
+    );
+    assert_eq!(common::number(&mut vm, "run"), 30.0);
+    assert_eq!(&*common::string(vm.call_global("text", &[])), "hello!");
+}
+
+#[test]
+fn native_error_is_a_runtime_error() {
+    let mut vm = VM::new();
+    vm.define_native("sum", 2, sum);
+    let error = common::runtime_error_in(&mut vm, "fun f() {\n  return sum(1, nil);\n}\nf();");
+    assert_eq!(error.message, "sum takes two numbers");
+    let trace: Vec<String> = error.trace.iter().map(|frame| frame.to_string()).collect();
+    assert_eq!(trace, vec!["[line 2] in <fn f>", "[line 4] in <script>"]);
+}
+
+#[test]
+fn natives_are_values() {
+    let mut vm = VM::new();
+    vm.define_native("sum", 2, sum);
+    common::run_in(&mut vm, "var add = sum;\nfun run() { return add(2, 3); }");
+    assert_eq!(common::number(&mut vm, "run"), 5.0);
+}
@@ -0,0 +1,104 @@
+mod common;
+
+use rlox::Value;
+
+fn source(expression: &str) -> String {
+    format!("fun run() {{ return {}; }}", expression)
+}
+
+fn eval(expression: &str) -> Result<Value, String> {
+    let mut vm = common::run(&source(expression));
+    vm.call_global("run", &[]).map_err(|e| e.message)
+}
+
+fn number(expression: &str) -> f64 {
+    common::call_number(&source(expression), "run")
+}
+
+#[test]
+fn modulo_is_floored() {
+    assert_eq!(number("7 % 3"), 1.0);
+    assert_eq!(number("-7 % 3"), 2.0);
+    assert_eq!(number("7 % -3"), -2.0);
+    assert_eq!(number("5.5 % 2"), 1.5);
+}
+
+#[test]
+fn floor_division() {
+    assert_eq!(number("7 // 2"), 3.0);
+    assert_eq!(number("-7 // 2"), -4.0);
+    assert_eq!(number("8 // 2 / 2"), 2.0);
+    // x == (x // y) * y + x % y
+    assert_eq!(number("(-7 // 3) * 3 + -7 % 3"), -7.0);
+}
+
+#[test]
+fn exponent_binds_right_and_above_unary() {
+    assert_eq!(number("2 ** 10"), 1024.0);
+    assert_eq!(number("2 ** 3 ** 2"), 512.0);
+    assert_eq!(number("-2 ** 2"), -4.0);
+    assert_eq!(number("2 ** -1"), 0.5);
+    assert_eq!(number("1 + 2 * 3 ** 2 % 5"), 4.0);
+}
+
+#[test]
+fn operands_must_be_numbers() {
+    assert_eq!(eval("\"a\" % 2").err().unwrap(), "Mod operation error");
+    assert_eq!(eval("nil ** 2").err().unwrap(), "Pow operation error");
+    assert_eq!(
+        eval("2 // true").err().unwrap(),
+        "Floor Div operation error"
+    );
+}
+
+#[test]
+fn slashes_still_start_comments() {
+    // '//' right after an operand on the same line divides, anywhere else it
+    // starts a comment
+    let source = "// leading comment\n\
+                  fun run() { // after a brace\n\
+                    var a = 7 // 2; // after a semicolon\n\
+                    var b = (a)//2;\n\
+                    var c = ~a; //~ next to tilde\n\
+                    return a * 100 + b * 10 + c\n\
+                    // on its own line after an operand\n\
+                    ;\n\
+                  }\n";
+    assert_eq!(common::call_number(source, "run"), 300.0 + 10.0 - 4.0);
+}
+
+#[test]
+fn bitwise_on_integers() {
+    assert_eq!(number("12 & 10"), 8.0);
+    assert_eq!(number("12 | 10"), 14.0);
+    assert_eq!(number("12 ^ 10"), 6.0);
+    assert_eq!(number("~5"), -6.0);
+    assert_eq!(number("1 << 10"), 1024.0);
+    assert_eq!(number("-16 >> 2"), -4.0);
+}
+
+#[test]
+fn bitwise_precedence_is_c_like() {
+    // shifts bind tighter than comparison, looser than '+'
+    assert_eq!(number("1 << 2 + 1"), 8.0);
+    // '|' < '^' < '&'
+    assert_eq!(number("1 | 6 ^ 3 & 5"), 7.0);
+    // bitwise operators bind looser than '=='
+    match eval("3 & 1 == 1") {
+        Err(message) => assert_eq!(message, "BitAnd operation error"),
+        Ok(value) => panic!("expected error, got {}", value.to_string()),
+    }
+    assert_eq!(number("~1 + 1"), -1.0);
+}
+
+#[test]
+fn bitwise_needs_integral_numbers() {
+    assert_eq!(eval("1.5 & 1").err().unwrap(), "BitAnd operation error");
+    assert_eq!(eval("\"a\" | 1").err().unwrap(), "BitOr operation error");
+    assert_eq!(eval("~0.5").err().unwrap(), "BitNot operation error");
+    assert_eq!(eval("1 << 64").err().unwrap(), "Shift Left operation error");
+    assert_eq!(
+        eval("1 >> -1").err().unwrap(),
+        "Shift Right operation error"
+    );
+}
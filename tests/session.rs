@@ -0,0 +1,67 @@
+mod common;
+
+use rlox::{InterpretResult, Session};
+
+fn eval(session: &mut Session, source: &str) {
+    match session.eval(source) {
+        InterpretResult::Success => {}
+        result => panic!("{}", result.to_string()),
+    }
+}
+
+#[test]
+fn globals_persist_between_lines() {
+    let mut session = Session::new();
+    eval(&mut session, "var x = 2;");
+    eval(&mut session, "fun f() { return x * 3; }");
+    eval(&mut session, "x = x + 1;");
+    assert_eq!(common::number(session.vm(), "f"), 9.0);
+}
+
+#[test]
+fn errors_do_not_end_the_session() {
+    let mut session = Session::new();
+    assert!(matches!(
+        session.eval("var = 1;"),
+        InterpretResult::CompileError(_)
+    ));
+    assert!(matches!(
+        session.eval("fun f() { return 1 + nil; } f();"),
+        InterpretResult::RuntimeError(_)
+    ));
+    eval(&mut session, "fun g() { return 4; }");
+    assert_eq!(common::number(session.vm(), "g"), 4.0);
+}
+
+#[test]
+fn expression_statements_are_echoed() {
+    let mut session = Session::new();
+    eval(&mut session, "1 + 2;");
+    eval(&mut session, "var s = \"a\";");
+    eval(&mut session, "s + \"b\";");
+    eval(&mut session, "fun f() { return s; }");
+    assert_eq!(&*common::string(session.vm().call_global("f", &[])), "a");
+}
+
+#[test]
+fn unbalanced_brackets_are_incomplete() {
+    assert!(Session::is_incomplete("fun f() {"));
+    assert!(Session::is_incomplete("print (1 +"));
+    assert!(Session::is_incomplete(
+        "class A {\n  m() {\n    return 1;\n  }"
+    ));
+    assert!(!Session::is_incomplete("fun f() { return 1; }"));
+    assert!(!Session::is_incomplete("print 1;"));
+}
+
+#[test]
+fn open_string_is_incomplete() {
+    assert!(Session::is_incomplete("print \"abc"));
+    assert!(!Session::is_incomplete("print \"abc\";"));
+}
+
+#[test]
+fn extra_closing_bracket_is_not_incomplete() {
+    // the compiler reports it instead of waiting for more lines
+    assert!(!Session::is_incomplete("print 1); {"));
+}
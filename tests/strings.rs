@@ -0,0 +1,112 @@
+mod common;
+
+use common::{boolean, run, string};
+use rlox::{InterpretResult, Value};
+use std::rc::Rc;
+
+#[test]
+fn equal_strings_share_one_allocation() {
+    let source = "fun literal() { return \"hello\"; }\nfun built() { return \"hel\" + \"lo\"; }";
+    let mut vm = run(source);
+    let literal = string(vm.call_global("literal", &[]));
+    let built = string(vm.call_global("built", &[]));
+    assert!(Rc::ptr_eq(&literal, &built));
+}
+
+#[test]
+fn host_strings_compare_equal() {
+    let source = "fun same(a, b) { return a == b; }\nfun is_key(a) { return a == \"key\"; }";
+    let mut vm = run(source);
+    // strings built outside the vm are interned on the way in
+    let key = Value::String(Rc::from("key"));
+    assert!(boolean(
+        vm.call_global("is_key", std::slice::from_ref(&key))
+    ));
+    let interned = vm.intern_string("key");
+    assert!(boolean(vm.call_global("same", &[key, interned])));
+    let other = vm.intern_string("other");
+    assert!(!boolean(vm.call_global("is_key", &[other])));
+}
+
+#[test]
+fn separately_compiled_functions_use_the_vm_table() {
+    let mut vm = run("var first = \"shared\";");
+    let function = common::compile("fun check() { return first == \"shared\"; }");
+    assert!(matches!(
+        vm.interpret_function(function),
+        InterpretResult::Success
+    ));
+    assert!(boolean(vm.call_global("check", &[])));
+}
+
+fn literal(source: &str) -> Rc<str> {
+    let mut vm = run(&format!("fun literal() {{ return {}; }}", source));
+    string(vm.call_global("literal", &[]))
+}
+
+fn scan_error(source: &str) -> String {
+    common::compile_errors(source)[0].message.clone()
+}
+
+#[test]
+fn escape_sequences() {
+    assert_eq!(&*literal(r#""a\tb\nc\r\0""#), "a\tb\nc\r\0");
+    assert_eq!(&*literal(r#""say \"hi\" \\ done""#), "say \"hi\" \\ done");
+    assert_eq!(&*literal(r#""\u{48}\u{e9}\u{1F600}""#), "H\u{e9}\u{1F600}");
+}
+
+#[test]
+fn escaped_strings_are_interned() {
+    let source = "fun a() { return \"x\\ny\"; }\nfun b() { return \"x\n\" + \"y\"; }";
+    let mut vm = run(source);
+    assert!(Rc::ptr_eq(
+        &string(vm.call_global("a", &[])),
+        &string(vm.call_global("b", &[]))
+    ));
+}
+
+#[test]
+fn raw_strings_keep_backslashes() {
+    assert_eq!(&*literal(r#"r"C:\dir\n""#), "C:\\dir\\n");
+    assert_eq!(&*literal("r\"two\nlines\""), "two\nlines");
+    // a plain 'r' is still an identifier
+    let source = "var r = \"id\"; fun literal() { return r; }";
+    let mut vm = run(source);
+    assert_eq!(&*string(vm.call_global("literal", &[])), "id");
+}
+
+#[test]
+fn invalid_escapes() {
+    assert_eq!(
+        scan_error(r#"print "\q";"#),
+        "Scan Lex error: invalid escape sequence '\\q'"
+    );
+    assert_eq!(
+        scan_error(r#"print "\u{110000}";"#),
+        "Scan Lex error: invalid unicode escape '\\u{110000}'"
+    );
+    assert_eq!(
+        scan_error(r#"print "\u{}";"#),
+        "Scan Lex error: invalid unicode escape '\\u{}'"
+    );
+    assert_eq!(
+        scan_error(r#"print "open \";"#),
+        "Scan Lex error: unterminated string"
+    );
+}
+
+#[test]
+fn shared_chunks_are_interned_too() {
+    let mut vm = run("var first = \"shared\";");
+    let function = common::compile("fun check() { return first == \"shared\"; }");
+    // the host keeps its own handles on the script chunk and the nested function
+    let _handles = (
+        function.chunk.clone(),
+        function.chunk.functions()[0].clone(),
+    );
+    assert!(matches!(
+        vm.interpret_function(function),
+        InterpretResult::Success
+    ));
+    assert!(boolean(vm.call_global("check", &[])));
+}
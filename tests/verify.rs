@@ -0,0 +1,193 @@
+mod common;
+
+use rlox::{Function, InterpretResult, VM};
+
+const NIL: u8 = 1;
+const TRUE: u8 = 2;
+const NUMBER: u8 = 4;
+const ADDITION: u8 = 9;
+const PRINT: u8 = 16;
+const JUMP_FALSE: u8 = 23;
+const JUMP: u8 = 24;
+const CLOSURE: u8 = 6;
+const POP: u8 = 17;
+const DEFINE_GLOBAL: u8 = 18;
+const GET_GLOBAL: u8 = 19;
+const CALL: u8 = 26;
+const GET_UPVALUE: u8 = 27;
+const CLOSE_UPVALUE: u8 = 29;
+const RETURN: u8 = 0;
+
+// one encoded function: name, params, upvalues, then its chunk
+fn function(
+    params_num: u32,
+    upvalue_count: u32,
+    code: &[u8],
+    numbers: &[f64],
+    strings: &[&str],
+    functions: &[Vec<u8>],
+) -> Vec<u8> {
+    let mut bytes: Vec<u8> = Vec::new();
+    for field in [0u32, params_num, upvalue_count, code.len() as u32] {
+        bytes.extend_from_slice(&field.to_le_bytes());
+    }
+    bytes.extend_from_slice(code);
+    bytes.extend_from_slice(&(code.len() as u32).to_le_bytes());
+    for _ in code {
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+    }
+    bytes.extend_from_slice(&(numbers.len() as u32).to_le_bytes());
+    for number in numbers {
+        bytes.extend_from_slice(&number.to_bits().to_le_bytes());
+    }
+    bytes.extend_from_slice(&(strings.len() as u32).to_le_bytes());
+    for string in strings {
+        bytes.extend_from_slice(&(string.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(string.as_bytes());
+    }
+    // no local variable slots
+    bytes.extend_from_slice(&0u32.to_le_bytes());
+    bytes.extend_from_slice(&(functions.len() as u32).to_le_bytes());
+    for function in functions {
+        bytes.extend_from_slice(function);
+    }
+    bytes
+}
+
+fn file(script: Vec<u8>) -> Vec<u8> {
+    let mut bytes: Vec<u8> = b"RLOX".to_vec();
+    bytes.extend_from_slice(&1u16.to_le_bytes());
+    bytes.extend_from_slice(&script);
+    bytes
+}
+
+// a bytecode file holding one script function with the given code and numbers
+fn script(code: &[u8], numbers: &[f64]) -> Vec<u8> {
+    file(function(0, 0, code, numbers, &[], &[]))
+}
+
+fn load_error(code: &[u8], numbers: &[f64]) -> String {
+    common::load_error(&script(code, numbers))
+}
+
+#[test]
+fn hand_written_script_runs() {
+    let mut vm = VM::new();
+    let bytes: Vec<u8> = script(
+        &[NUMBER, 0, NUMBER, 0, ADDITION, PRINT, NIL, RETURN],
+        &[1.5],
+    );
+    assert!(matches!(
+        vm.interpret_bytes(&bytes),
+        InterpretResult::Success
+    ));
+}
+
+#[test]
+fn compiled_code_verifies() {
+    let source = "class A { init(n) { this.n = n; } get() { return this.n; } }\n\
+                  class B < A { get() { return super.get() + 1; } }\n\
+                  fun outer() { var x = 1; fun inner() { x = x + 1; return inner; } return inner; }\n\
+                  for (var i = 0; i < 3 and true or false; i = i + 1) { if (i == 1) print i; else print -i; }\n\
+                  print B(2).get();";
+    assert!(common::compile(source).verify().is_ok());
+}
+
+#[test]
+fn unknown_opcode() {
+    assert_eq!(
+        load_error(&[200, NIL, RETURN], &[]),
+        "Invalid bytecode in <script> at offset 0: Invalid opcode 200"
+    );
+}
+
+#[test]
+fn operands_stay_in_bounds() {
+    assert!(load_error(&[NUMBER, 1, PRINT, NIL, RETURN], &[1.0])
+        .ends_with("OP_NUMBER index 1 is outside the 1 number constants"));
+    assert!(load_error(&[NIL, RETURN, NUMBER], &[1.0]).ends_with("OP_NUMBER is cut off"));
+    assert!(load_error(&[GET_UPVALUE, 0, PRINT, NIL, RETURN], &[])
+        .ends_with("OP_GET_UPVALUE slot 0 is outside the 0 upvalues"));
+}
+
+#[test]
+fn jumps_land_on_instructions() {
+    assert!(
+        load_error(&[JUMP, 1, 0, NUMBER, 0, PRINT, NIL, RETURN], &[1.0])
+            .ends_with("Jump target 4 is not the start of an instruction")
+    );
+    assert!(load_error(&[JUMP, 9, 0, NIL, RETURN], &[])
+        .ends_with("Jump target 12 is not the start of an instruction"));
+}
+
+#[test]
+fn stack_depth_is_balanced() {
+    assert!(load_error(&[ADDITION, NIL, RETURN], &[])
+        .ends_with("OP_ADDITION needs 2 stack values but only 1 are there"));
+    // the taken branch skips one push
+    assert!(load_error(&[TRUE, JUMP_FALSE, 1, 0, NIL, NIL, RETURN], &[])
+        .ends_with("does not match depth 3 on another path"));
+    assert!(load_error(&[NIL, PRINT], &[]).ends_with("Execution runs past the end of the chunk"));
+}
+
+#[test]
+fn script_has_no_upvalues() {
+    let script: Vec<u8> = function(0, 1, &[GET_UPVALUE, 0, RETURN], &[], &[], &[]);
+    assert_eq!(
+        common::load_error(&file(script)),
+        "Invalid bytecode in <script>: a script takes no parameters or upvalues, found 0 and 1"
+    );
+}
+
+#[test]
+fn script_has_no_parameters() {
+    // the closure captures slot 2, which only exists if the script had 2 arguments
+    let inner: Vec<u8> = function(0, 1, &[NIL, RETURN], &[], &[], &[]);
+    let script: Vec<u8> = function(
+        2,
+        0,
+        &[CLOSURE, 0, 1, 2, POP, NIL, RETURN],
+        &[],
+        &[],
+        &[inner],
+    );
+    assert_eq!(
+        common::load_error(&file(script)),
+        "Invalid bytecode in <script>: a script takes no parameters or upvalues, found 2 and 0"
+    );
+}
+
+// a closure over local slot 5 that outlives the slot, then gets called
+fn dangling_capture(close: &[u8]) -> Vec<u8> {
+    let inner: Vec<u8> = function(0, 1, &[GET_UPVALUE, 0, RETURN], &[], &[], &[]);
+    let mut code: Vec<u8> = vec![NIL, NIL, NIL, NIL, NIL, CLOSURE, 0, 1, 5, DEFINE_GLOBAL, 0];
+    code.extend_from_slice(close);
+    code.extend_from_slice(&[
+        POP, POP, POP, POP, GET_GLOBAL, 0, CALL, 0, PRINT, NIL, RETURN,
+    ]);
+    file(function(0, 0, &code, &[], &["f"], &[inner]))
+}
+
+#[test]
+fn captured_slot_must_be_closed() {
+    let bytes: Vec<u8> = dangling_capture(&[POP]);
+    assert_eq!(
+        common::load_error(&bytes),
+        "Invalid bytecode in <script> at offset 11: OP_POP drops captured local slot 5 without closing it"
+    );
+    // run without the verifier the vm reports the dangling upvalue instead of panicking
+    let mut vm = VM::new();
+    match vm.interpret_function(Function::from_bytes(&bytes).unwrap()) {
+        InterpretResult::RuntimeError(error) => assert_eq!(
+            error.message,
+            "Upvalue refers to stack slot 5 past the stack"
+        ),
+        result => panic!("expected runtime error, got {}", result.to_string()),
+    }
+
+    let mut vm = VM::new();
+    assert!(matches!(
+        vm.interpret_bytes(&dangling_capture(&[CLOSE_UPVALUE])),
+        InterpretResult::Success
+    ));
+}